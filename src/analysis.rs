@@ -0,0 +1,978 @@
+//! Analyses over toy-language AST nodes, which run without translating them
+//! into Cranelift IR.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use frontend::*;
+
+/// The value of an expression which can be computed without running the
+/// program: an integer literal, a host-provided constant, or arithmetic on
+/// them, other than division. Arithmetic wraps, as it does when it's run.
+pub fn constant_value(expr: &Expr, constants: &HashMap<String, i64>) -> Option<i64> {
+    let value = |expr| constant_value(expr, constants);
+    match *expr {
+        Expr::Literal(ref literal, radix) => i64::from_str_radix(literal, radix).ok(),
+        Expr::Bool(value) => Some(value as i64),
+        Expr::Identifier(ref name) => constants.get(name).cloned(),
+        Expr::Neg(ref expr) => Some(value(expr)?.wrapping_neg()),
+        Expr::Not(ref expr) => Some((value(expr)? == 0) as i64),
+        Expr::Cast(ref expr, ValueType::Int) => value(expr),
+        Expr::Cast(ref expr, ValueType::I32) => Some(i64::from(value(expr)? as i32)),
+        Expr::Add(ref lhs, ref rhs) => Some(value(lhs)?.wrapping_add(value(rhs)?)),
+        Expr::Sub(ref lhs, ref rhs) => Some(value(lhs)?.wrapping_sub(value(rhs)?)),
+        Expr::Mul(ref lhs, ref rhs) => Some(value(lhs)?.wrapping_mul(value(rhs)?)),
+        Expr::BitAnd(ref lhs, ref rhs) => Some(value(lhs)? & value(rhs)?),
+        Expr::BitOr(ref lhs, ref rhs) => Some(value(lhs)? | value(rhs)?),
+        Expr::BitXor(ref lhs, ref rhs) => Some(value(lhs)? ^ value(rhs)?),
+        Expr::Shl(ref lhs, ref rhs) => Some(value(lhs)?.wrapping_shl(value(rhs)? as u32)),
+        Expr::Shr(ref lhs, ref rhs) => Some(value(lhs)?.wrapping_shr(value(rhs)? as u32)),
+        Expr::UShr(ref lhs, ref rhs) => {
+            Some((value(lhs)? as u64).wrapping_shr(value(rhs)? as u32) as i64)
+        }
+        _ => None,
+    }
+}
+
+/// The type of value `expr` produces, given the types of the variables which
+/// aren't integers. Arithmetic produces the type `arithmetic_type` gives,
+/// except that adding to or subtracting from a pointer gives a pointer, and
+/// subtracting pointers an integer. An if-else produces the type of its
+/// bodies if they agree, casts and conversions the type they convert to,
+/// `&x`, `array` and `alloc` pointers, and `true`, `false`, comparisons and
+/// logical operators bools. Everything else, including other calls, produces
+/// integers.
+pub fn type_of(expr: &Expr, types: &HashMap<String, ValueType>) -> ValueType {
+    match *expr {
+        Expr::Float(_) => ValueType::F64,
+        Expr::Bool(_)
+        | Expr::Eq(..)
+        | Expr::Ne(..)
+        | Expr::Lt(..)
+        | Expr::Le(..)
+        | Expr::Gt(..)
+        | Expr::Ge(..)
+        | Expr::Not(_)
+        | Expr::And(..)
+        | Expr::Or(..) => ValueType::Bool,
+        Expr::Identifier(ref name) => types.get(name).cloned().unwrap_or(ValueType::Int),
+        Expr::Assign(_, ref expr) | Expr::Neg(ref expr) => type_of(expr, types),
+        Expr::Cast(_, ty) => ty,
+        Expr::GlobalDataAddr(_) => ValueType::Ptr,
+        Expr::Add(ref lhs, ref rhs) => match (type_of(lhs, types), type_of(rhs, types)) {
+            (ValueType::Ptr, _) | (_, ValueType::Ptr) => ValueType::Ptr,
+            (lhs, rhs) => arithmetic_type(lhs, rhs),
+        },
+        Expr::Sub(ref lhs, ref rhs) => match (type_of(lhs, types), type_of(rhs, types)) {
+            (ValueType::Ptr, ValueType::Ptr) => ValueType::Int,
+            (ValueType::Ptr, _) => ValueType::Ptr,
+            (lhs, rhs) => arithmetic_type(lhs, rhs),
+        },
+        Expr::Mul(ref lhs, ref rhs)
+        | Expr::Div(ref lhs, ref rhs)
+        | Expr::Mod(ref lhs, ref rhs)
+        | Expr::BitAnd(ref lhs, ref rhs)
+        | Expr::BitOr(ref lhs, ref rhs)
+        | Expr::BitXor(ref lhs, ref rhs)
+        | Expr::Shl(ref lhs, ref rhs)
+        | Expr::Shr(ref lhs, ref rhs)
+        | Expr::UShr(ref lhs, ref rhs) => arithmetic_type(type_of(lhs, types), type_of(rhs, types)),
+        Expr::IfElse(_, ref then_body, ref else_body) => {
+            let ty = body_type(then_body, types);
+            if ty == body_type(else_body, types) {
+                ty
+            } else {
+                ValueType::Int
+            }
+        }
+        Expr::Call(ref name, _) => match Builtin::lookup(name) {
+            Some(Builtin::F32) => ValueType::F32,
+            Some(Builtin::F64) => ValueType::F64,
+            Some(Builtin::I32) => ValueType::I32,
+            Some(Builtin::Array) | Some(Builtin::Alloc) => ValueType::Ptr,
+            _ => ValueType::Int,
+        },
+        _ => ValueType::Int,
+    }
+}
+
+/// The type of arithmetic on operands of types `lhs` and `rhs`. Mixing
+/// integers of different widths gives the wider type, and mixing signed and
+/// unsigned integers of the same width gives the unsigned one, as in C.
+/// Likewise, mixing an integer with a float gives the float, and mixing the
+/// two float types gives `f64`. Otherwise, it's the type of the left operand.
+/// Pointers and bools are treated as integers.
+pub fn arithmetic_type(lhs: ValueType, rhs: ValueType) -> ValueType {
+    let integer = |ty| match ty {
+        ValueType::Ptr | ValueType::Bool => ValueType::Int,
+        ty => ty,
+    };
+    let (lhs, rhs) = (integer(lhs), integer(rhs));
+    if lhs == ValueType::F64 || rhs == ValueType::F64 {
+        return ValueType::F64;
+    }
+    if lhs == ValueType::F32 || rhs == ValueType::F32 {
+        return ValueType::F32;
+    }
+    // Integers are ranked from narrowest to widest, with the unsigned ones
+    // after the signed ones of the same width.
+    let rank = |ty| match ty {
+        ValueType::I32 => Some(0),
+        ValueType::U32 => Some(1),
+        ValueType::Int => Some(2),
+        ValueType::U64 => Some(3),
+        ValueType::Ptr | ValueType::Bool | ValueType::F32 | ValueType::F64 => None,
+    };
+    match (rank(lhs), rank(rhs)) {
+        (Some(l), Some(r)) if r > l => rhs,
+        _ => lhs,
+    }
+}
+
+/// The type of value a body of statements produces, which is that of its
+/// last statement, or an integer if it's empty.
+pub fn body_type(body: &[Expr], types: &HashMap<String, ValueType>) -> ValueType {
+    match body.last() {
+        Some(expr) => type_of(expr, types),
+        None => ValueType::Int,
+    }
+}
+
+/// Find the types of the variables in `stmts` which don't hold integers,
+/// along with those `declared` to have a type. Each of the others takes the
+/// type of the first value of another type assigned to it. Since one variable
+/// can be assigned from another, this repeats until nothing more is found.
+pub fn variable_types(
+    declared: &HashMap<String, ValueType>,
+    stmts: &[Expr],
+) -> HashMap<String, ValueType> {
+    let mut assignments = Assignments::default();
+    for stmt in stmts {
+        assignments.expr(stmt);
+    }
+    let mut types = declared.clone();
+    loop {
+        let mut found = Vec::new();
+        for &(name, value) in &assignments.found {
+            if types.contains_key(name) || found.iter().any(|&(n, _)| n == name) {
+                continue;
+            }
+            match type_of(value, &types) {
+                ValueType::Int => {}
+                ty => found.push((name, ty)),
+            }
+        }
+        if found.is_empty() {
+            return types;
+        }
+        types.extend(found.into_iter().map(|(name, ty)| (name.to_string(), ty)));
+    }
+}
+
+/// The type of the value `function` returns.
+pub fn return_type(function: &Function) -> ValueType {
+    let types = variable_types(&function.types, &function.stmts);
+    types
+        .get(&function.the_return)
+        .cloned()
+        .unwrap_or(ValueType::Int)
+}
+
+/// The variables assigned anywhere in `expr`, in the order they're assigned,
+/// which may include some more than once.
+pub fn assigned_variables(expr: &Expr) -> Vec<&str> {
+    let mut assignments = Assignments::default();
+    assignments.expr(expr);
+    assignments
+        .found
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Every assignment in a function, with the value assigned.
+#[derive(Default)]
+struct Assignments<'a> {
+    found: Vec<(&'a str, &'a Expr)>,
+}
+
+impl<'a> Assignments<'a> {
+    fn expr(&mut self, expr: &'a Expr) {
+        match *expr {
+            Expr::Literal(..)
+            | Expr::Bool(_)
+            | Expr::Float(_)
+            | Expr::Identifier(_)
+            | Expr::GlobalDataAddr(_)
+            | Expr::Str(_)
+            | Expr::TypeOf(_)
+            | Expr::Break
+            | Expr::Continue
+            | Expr::Label(_)
+            | Expr::Goto(_) => {}
+            Expr::Assign(ref name, ref value) => {
+                self.found.push((name, value));
+                self.expr(value);
+            }
+            Expr::Eq(ref lhs, ref rhs)
+            | Expr::Ne(ref lhs, ref rhs)
+            | Expr::Lt(ref lhs, ref rhs)
+            | Expr::Le(ref lhs, ref rhs)
+            | Expr::Gt(ref lhs, ref rhs)
+            | Expr::Ge(ref lhs, ref rhs)
+            | Expr::Add(ref lhs, ref rhs)
+            | Expr::Sub(ref lhs, ref rhs)
+            | Expr::Mul(ref lhs, ref rhs)
+            | Expr::Div(ref lhs, ref rhs)
+            | Expr::Mod(ref lhs, ref rhs)
+            | Expr::BitAnd(ref lhs, ref rhs)
+            | Expr::BitOr(ref lhs, ref rhs)
+            | Expr::BitXor(ref lhs, ref rhs)
+            | Expr::Shl(ref lhs, ref rhs)
+            | Expr::Shr(ref lhs, ref rhs)
+            | Expr::UShr(ref lhs, ref rhs)
+            | Expr::Index(ref lhs, ref rhs)
+            | Expr::DerefAssign(ref lhs, ref rhs)
+            | Expr::And(ref lhs, ref rhs)
+            | Expr::Or(ref lhs, ref rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                self.expr(array);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::IfElse(ref condition, ref then_body, ref else_body)
+            | Expr::WhileLoop(ref condition, ref then_body, ref else_body) => {
+                self.expr(condition);
+                for stmt in then_body.iter().chain(else_body) {
+                    self.expr(stmt);
+                }
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+                self.expr(condition);
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                self.expr(count);
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+            }
+            Expr::Call(_, ref args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Neg(ref expr)
+            | Expr::Not(ref expr)
+            | Expr::Deref(ref expr)
+            | Expr::Cast(ref expr, _) => self.expr(expr),
+        }
+    }
+}
+
+/// Check that a function's default parameter values are all constants, and
+/// that every parameter after one with a default also has one.
+pub fn check_defaults(function: &Function, constants: &HashMap<String, i64>) -> Result<(), String> {
+    let mut defaulted = None;
+    for (param, default) in function.params.iter().zip(&function.defaults) {
+        match *default {
+            Some(ref default) => {
+                if constant_value(default, constants).is_none() {
+                    return Err(format!(
+                        "the default value of `{}` in `{}` isn't a constant",
+                        param, function.name
+                    ));
+                }
+                defaulted = Some(param);
+            }
+            None => {
+                if let Some(defaulted) = defaulted {
+                    return Err(format!(
+                        "`{}` in `{}` has no default value, but comes after `{}`, which has one",
+                        param, function.name, defaulted
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find the labels in a function, with the index of the top-level statement
+/// each one is. Labels may only appear among a function's top-level
+/// statements, so that jumping to one never enters the middle of an if/else
+/// or a loop, and each `goto` must name one of them.
+pub fn labels(stmts: &[Expr]) -> Result<HashMap<String, usize>, String> {
+    let mut labels = HashMap::new();
+    let mut jumps = Jumps::default();
+    for (index, stmt) in stmts.iter().enumerate() {
+        match *stmt {
+            Expr::Label(ref name) => {
+                if labels.insert(name.clone(), index).is_some() {
+                    return Err(format!("label `{}` is defined more than once", name));
+                }
+            }
+            _ => jumps.expr(stmt),
+        }
+    }
+    if let Some(name) = jumps.nested_labels.first() {
+        return Err(format!(
+            "label `{}` is inside another statement, but labels may only be at the top level of a function",
+            name
+        ));
+    }
+    if let Some(name) = jumps.gotos.iter().find(|name| !labels.contains_key(*name)) {
+        return Err(format!("there is no label `{}` to go to", name));
+    }
+    Ok(labels)
+}
+
+/// The `goto`s in a function, and any labels which aren't at its top level.
+#[derive(Default)]
+struct Jumps {
+    gotos: Vec<String>,
+    nested_labels: Vec<String>,
+}
+
+impl Jumps {
+    fn expr(&mut self, expr: &Expr) {
+        match *expr {
+            Expr::Literal(..)
+            | Expr::Bool(_)
+            | Expr::Float(_)
+            | Expr::Identifier(_)
+            | Expr::GlobalDataAddr(_)
+            | Expr::Str(_)
+            | Expr::TypeOf(_)
+            | Expr::Break
+            | Expr::Continue => {}
+            Expr::Label(ref name) => self.nested_labels.push(name.clone()),
+            Expr::Goto(ref name) => self.gotos.push(name.clone()),
+            Expr::Assign(_, ref expr)
+            | Expr::Neg(ref expr)
+            | Expr::Not(ref expr)
+            | Expr::Deref(ref expr)
+            | Expr::Cast(ref expr, _) => self.expr(expr),
+            Expr::Eq(ref lhs, ref rhs)
+            | Expr::Ne(ref lhs, ref rhs)
+            | Expr::Lt(ref lhs, ref rhs)
+            | Expr::Le(ref lhs, ref rhs)
+            | Expr::Gt(ref lhs, ref rhs)
+            | Expr::Ge(ref lhs, ref rhs)
+            | Expr::Add(ref lhs, ref rhs)
+            | Expr::Sub(ref lhs, ref rhs)
+            | Expr::Mul(ref lhs, ref rhs)
+            | Expr::Div(ref lhs, ref rhs)
+            | Expr::Mod(ref lhs, ref rhs)
+            | Expr::BitAnd(ref lhs, ref rhs)
+            | Expr::BitOr(ref lhs, ref rhs)
+            | Expr::BitXor(ref lhs, ref rhs)
+            | Expr::Shl(ref lhs, ref rhs)
+            | Expr::Shr(ref lhs, ref rhs)
+            | Expr::UShr(ref lhs, ref rhs)
+            | Expr::Index(ref lhs, ref rhs)
+            | Expr::DerefAssign(ref lhs, ref rhs)
+            | Expr::And(ref lhs, ref rhs)
+            | Expr::Or(ref lhs, ref rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                self.expr(array);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::IfElse(ref condition, ref then_body, ref else_body)
+            | Expr::WhileLoop(ref condition, ref then_body, ref else_body) => {
+                self.expr(condition);
+                for stmt in then_body.iter().chain(else_body) {
+                    self.expr(stmt);
+                }
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+                self.expr(condition);
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                self.expr(count);
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+            }
+            Expr::Call(_, ref args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+        }
+    }
+}
+
+/// Find assignments whose value is never read, in source order.
+///
+/// This is a classic backwards liveness analysis: walking the statements from
+/// the end of the function towards the start, we track the set of variables
+/// whose current value may still be read. An assignment to a variable which
+/// isn't in that set is a dead store, unless the variable's address is taken.
+/// The return variable is read when the function exits, so it starts out
+/// live.
+pub fn dead_stores(the_return: &str, stmts: &[Expr]) -> Vec<String> {
+    let mut liveness = Liveness {
+        addressed: address_taken(stmts).into_iter().collect(),
+        record: false,
+        dead: Vec::new(),
+        loops: Vec::new(),
+        labels: HashMap::new(),
+    };
+    let mut exit_live = HashSet::new();
+    exit_live.insert(the_return.to_string());
+
+    // A `goto` may jump backwards, so as with a loop, iterate until the set
+    // of variables live at each label stops growing, then make one more pass
+    // to record the dead stores.
+    loop {
+        let labels = liveness.labels.clone();
+        liveness.stmts(stmts, &mut exit_live.clone());
+        if liveness.labels == labels {
+            break;
+        }
+    }
+    liveness.record = true;
+    liveness.stmts(stmts, &mut exit_live);
+
+    // The walk visits stores in reverse evaluation order.
+    liveness.dead.reverse();
+    liveness.dead
+}
+
+struct Liveness {
+    /// The variables whose address is taken, which may be read through it
+    /// at any time, so that no store to them is dead.
+    addressed: HashSet<String>,
+    /// Whether dead stores should be recorded. This is turned off while
+    /// iterating a loop to a fixed point, since a store may look dead until
+    /// the backedge has been accounted for.
+    record: bool,
+    dead: Vec<String>,
+    /// The variables live at the header of each enclosing loop, which is
+    /// where a `continue` goes, and after it, which is where a `break` goes,
+    /// innermost last.
+    loops: Vec<(HashSet<String>, HashSet<String>)>,
+    /// The variables live at each label seen so far.
+    labels: HashMap<String, HashSet<String>>,
+}
+
+impl Liveness {
+    /// Turn the set of variables live after `stmts` into the set live before.
+    fn stmts(&mut self, stmts: &[Expr], live: &mut HashSet<String>) {
+        for stmt in stmts.iter().rev() {
+            self.expr(stmt, live);
+        }
+    }
+
+    /// Turn the set of variables live after `expr` into the set live before.
+    /// Subexpressions are visited in the reverse of the order they're
+    /// evaluated in.
+    fn expr(&mut self, expr: &Expr, live: &mut HashSet<String>) {
+        match *expr {
+            // The operand of `typeof` is never evaluated.
+            Expr::Literal(..)
+            | Expr::Bool(_)
+            | Expr::Float(_)
+            | Expr::GlobalDataAddr(_)
+            | Expr::Str(_)
+            | Expr::TypeOf(_) => {}
+            Expr::Break => {
+                // Nothing after a `break` runs, so what's live is whatever is
+                // live after the loop it leaves.
+                *live = self.loops.last().map(|l| l.1.clone()).unwrap_or_default();
+            }
+            Expr::Continue => {
+                // Likewise, what's live after a `continue` is whatever is
+                // live at the header of its loop.
+                *live = self.loops.last().map(|l| l.0.clone()).unwrap_or_default();
+            }
+            Expr::Goto(ref name) => {
+                *live = self.labels.get(name).cloned().unwrap_or_default();
+            }
+            Expr::Label(ref name) => {
+                self.labels
+                    .entry(name.clone())
+                    .or_default()
+                    .extend(live.iter().cloned());
+            }
+            Expr::Identifier(ref name) => {
+                live.insert(name.clone());
+            }
+            Expr::Assign(ref name, ref value) => {
+                if !live.remove(name)
+                    && self.record
+                    && !self.addressed.contains(name)
+                    && !is_generated_store(name, value)
+                {
+                    self.dead.push(format!(
+                        "value assigned to `{}` is never read",
+                        source_name(name)
+                    ));
+                }
+                self.expr(value, live);
+            }
+            Expr::Eq(ref lhs, ref rhs)
+            | Expr::Ne(ref lhs, ref rhs)
+            | Expr::Lt(ref lhs, ref rhs)
+            | Expr::Le(ref lhs, ref rhs)
+            | Expr::Gt(ref lhs, ref rhs)
+            | Expr::Ge(ref lhs, ref rhs)
+            | Expr::Add(ref lhs, ref rhs)
+            | Expr::Sub(ref lhs, ref rhs)
+            | Expr::Mul(ref lhs, ref rhs)
+            | Expr::Div(ref lhs, ref rhs)
+            | Expr::Mod(ref lhs, ref rhs)
+            | Expr::BitAnd(ref lhs, ref rhs)
+            | Expr::BitOr(ref lhs, ref rhs)
+            | Expr::BitXor(ref lhs, ref rhs)
+            | Expr::Shl(ref lhs, ref rhs)
+            | Expr::Shr(ref lhs, ref rhs)
+            | Expr::UShr(ref lhs, ref rhs)
+            | Expr::Index(ref lhs, ref rhs)
+            | Expr::DerefAssign(ref lhs, ref rhs) => {
+                self.expr(rhs, live);
+                self.expr(lhs, live);
+            }
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                self.expr(value, live);
+                self.expr(index, live);
+                self.expr(array, live);
+            }
+            Expr::Neg(ref expr)
+            | Expr::Not(ref expr)
+            | Expr::Deref(ref expr)
+            | Expr::Cast(ref expr, _) => self.expr(expr, live),
+            Expr::And(ref lhs, ref rhs) | Expr::Or(ref lhs, ref rhs) => {
+                // The right operand may be skipped, so what's live after it
+                // stays live.
+                let mut rhs_live = live.clone();
+                self.expr(rhs, &mut rhs_live);
+                live.extend(rhs_live);
+                self.expr(lhs, live);
+            }
+            Expr::IfElse(ref condition, ref then_body, ref else_body) => {
+                let mut else_live = live.clone();
+                self.stmts(else_body, &mut else_live);
+                self.stmts(then_body, live);
+                live.extend(else_live);
+                self.expr(condition, live);
+            }
+            Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+                // The else-body runs when the condition is false, and a
+                // `break` skips it.
+                let mut else_live = live.clone();
+                self.stmts(else_body, &mut else_live);
+                self.loop_live(Some(condition), loop_body, &else_live, live);
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                self.do_while_live(loop_body, condition, live);
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                // The count is evaluated once, before the loop, which has no
+                // condition to test at its header.
+                let exit_live = live.clone();
+                self.loop_live(None, loop_body, &exit_live, live);
+                self.expr(count, live);
+            }
+            Expr::Call(_, ref args) => {
+                for arg in args.iter().rev() {
+                    self.expr(arg, live);
+                }
+            }
+        }
+    }
+
+    /// Turn the set of variables live after a loop into the set live at its
+    /// header, given the variables live when it stops because its condition
+    /// is false, or its count has run out.
+    fn loop_live(
+        &mut self,
+        condition: Option<&Expr>,
+        loop_body: &[Expr],
+        else_live: &HashSet<String>,
+        live: &mut HashSet<String>,
+    ) {
+        // The loop header is reached both from before the loop and from the
+        // end of the body, so start from nothing and iterate until the set of
+        // variables live at the header stops growing.
+        let record = self.record;
+        self.record = false;
+        let mut header_live = HashSet::new();
+        loop {
+            let next = self.loop_header(condition, loop_body, &header_live, else_live, live);
+            if next == header_live {
+                break;
+            }
+            header_live = next;
+        }
+        self.record = record;
+
+        // Now that the header's live set is known, make one more pass to
+        // record the dead stores.
+        *live = self.loop_header(condition, loop_body, &header_live, else_live, live);
+    }
+
+    /// Turn the set of variables live after a do-while loop into the set
+    /// live at the top of its body, iterating to a fixed point as
+    /// `loop_live` does.
+    fn do_while_live(&mut self, loop_body: &[Expr], condition: &Expr, live: &mut HashSet<String>) {
+        let exit_live = live.clone();
+        let record = self.record;
+        self.record = false;
+        let mut body_live = HashSet::new();
+        loop {
+            let next = self.do_while_body(loop_body, condition, &body_live, &exit_live);
+            if next == body_live {
+                break;
+            }
+            body_live = next;
+        }
+        self.record = record;
+        *live = self.do_while_body(loop_body, condition, &body_live, &exit_live);
+    }
+
+    /// Compute the variables live at the top of a do-while loop's body,
+    /// given a guess for that set, and the variables live after the loop.
+    /// The condition either goes back to the top of the body or leaves the
+    /// loop, and a `continue` goes to the condition.
+    fn do_while_body(
+        &mut self,
+        loop_body: &[Expr],
+        condition: &Expr,
+        body_live: &HashSet<String>,
+        exit_live: &HashSet<String>,
+    ) -> HashSet<String> {
+        let mut live = exit_live.clone();
+        live.extend(body_live.iter().cloned());
+        self.expr(condition, &mut live);
+        self.loops.push((live.clone(), exit_live.clone()));
+        self.stmts(loop_body, &mut live);
+        self.loops.pop();
+        live
+    }
+
+    /// Compute the variables live at a loop header, given a guess for that
+    /// set, the variables live when the loop stops without a `break`, and the
+    /// variables live after the loop exits.
+    fn loop_header(
+        &mut self,
+        condition: Option<&Expr>,
+        loop_body: &[Expr],
+        header_live: &HashSet<String>,
+        else_live: &HashSet<String>,
+        exit_live: &HashSet<String>,
+    ) -> HashSet<String> {
+        let mut live = header_live.clone();
+        self.loops.push((header_live.clone(), exit_live.clone()));
+        self.stmts(loop_body, &mut live);
+        self.loops.pop();
+        live.extend(else_live.iter().cloned());
+        if let Some(condition) = condition {
+            self.expr(condition, &mut live);
+        }
+        live
+    }
+}
+
+/// Find identifiers which are read but never bound, in the order they first
+/// appear. An identifier is bound if it's a parameter, the return variable,
+/// assigned anywhere in the function, or one of the host-provided constants.
+pub fn free_identifiers(function: &Function, constants: &HashMap<String, i64>) -> Vec<String> {
+    let mut names = Names::default();
+    names.bound.extend(function.params.iter().cloned());
+    names.bound.insert(function.the_return.clone());
+    for stmt in &function.stmts {
+        names.expr(stmt);
+    }
+
+    let Names { bound, used, .. } = names;
+    used.into_iter()
+        .filter(|name| !bound.contains(name) && !constants.contains_key(name))
+        .collect()
+}
+
+/// Find the names whose address is taken with `&`, in the order they first
+/// appear. Those which are the function's variables are kept in memory,
+/// rather than in registers, and the others are data objects.
+pub fn address_taken(stmts: &[Expr]) -> Vec<String> {
+    let mut names = Names::default();
+    for stmt in stmts {
+        names.expr(stmt);
+    }
+    names.addressed
+}
+
+/// The names bound and used in a function.
+#[derive(Default)]
+struct Names {
+    bound: HashSet<String>,
+    /// Each name read, without duplicates, in order of first appearance.
+    used: Vec<String>,
+    /// Each name whose address is taken, in the same way.
+    addressed: Vec<String>,
+}
+
+impl Names {
+    fn expr(&mut self, expr: &Expr) {
+        match *expr {
+            Expr::Literal(..) | Expr::Bool(_) | Expr::Float(_) | Expr::Str(_) | Expr::Break => {}
+            Expr::Continue | Expr::Label(_) | Expr::Goto(_) => {}
+            Expr::Identifier(ref name) => {
+                if !self.used.contains(name) {
+                    self.used.push(name.clone());
+                }
+            }
+            Expr::GlobalDataAddr(ref name) => {
+                // This may be the address of a data object instead.
+                if !self.addressed.contains(name) {
+                    self.addressed.push(name.clone());
+                }
+            }
+            Expr::Assign(ref name, ref value) => {
+                self.bound.insert(name.clone());
+                self.expr(value);
+            }
+            Expr::Eq(ref lhs, ref rhs)
+            | Expr::Ne(ref lhs, ref rhs)
+            | Expr::Lt(ref lhs, ref rhs)
+            | Expr::Le(ref lhs, ref rhs)
+            | Expr::Gt(ref lhs, ref rhs)
+            | Expr::Ge(ref lhs, ref rhs)
+            | Expr::Add(ref lhs, ref rhs)
+            | Expr::Sub(ref lhs, ref rhs)
+            | Expr::Mul(ref lhs, ref rhs)
+            | Expr::Div(ref lhs, ref rhs)
+            | Expr::Mod(ref lhs, ref rhs)
+            | Expr::BitAnd(ref lhs, ref rhs)
+            | Expr::BitOr(ref lhs, ref rhs)
+            | Expr::BitXor(ref lhs, ref rhs)
+            | Expr::Shl(ref lhs, ref rhs)
+            | Expr::Shr(ref lhs, ref rhs)
+            | Expr::UShr(ref lhs, ref rhs)
+            | Expr::Index(ref lhs, ref rhs)
+            | Expr::DerefAssign(ref lhs, ref rhs)
+            | Expr::And(ref lhs, ref rhs)
+            | Expr::Or(ref lhs, ref rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                self.expr(array);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::IfElse(ref condition, ref then_body, ref else_body) => {
+                self.expr(condition);
+                for stmt in then_body.iter().chain(else_body) {
+                    self.expr(stmt);
+                }
+            }
+            Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+                self.expr(condition);
+                for stmt in loop_body.iter().chain(else_body) {
+                    self.expr(stmt);
+                }
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+                self.expr(condition);
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                self.expr(count);
+                for stmt in loop_body {
+                    self.expr(stmt);
+                }
+            }
+            Expr::Call(_, ref args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            // A typo is still a typo where it isn't evaluated.
+            Expr::Neg(ref expr)
+            | Expr::Not(ref expr)
+            | Expr::Deref(ref expr)
+            | Expr::Cast(ref expr, _) => self.expr(expr),
+            Expr::TypeOf(ref expr) => self.expr(expr),
+        }
+    }
+}
+
+/// Check whether a function obviously never returns, because every path
+/// through it calls the function itself with the arguments it was given.
+///
+/// This is conservative: anything which might stop the recursion, such as
+/// a call to another function, a division which might trap, or changing a
+/// parameter, makes the analysis give up and report that it might return.
+pub fn always_recurses(function: &Function) -> bool {
+    let mut recursion = Recursion {
+        function,
+        modified: HashSet::new(),
+        opaque: false,
+    };
+    recursion.stmts(&function.stmts)
+}
+
+struct Recursion<'a> {
+    function: &'a Function,
+    /// The parameters which may have been assigned so far.
+    modified: HashSet<String>,
+    /// Whether something has been evaluated which the analysis can't see
+    /// through, after which nothing counts as a recursive call.
+    opaque: bool,
+}
+
+impl<'a> Recursion<'a> {
+    /// Whether evaluating `stmts` always makes an unchanged recursive call.
+    fn stmts(&mut self, stmts: &[Expr]) -> bool {
+        stmts.iter().any(|stmt| self.expr(stmt))
+    }
+
+    /// Whether evaluating `expr` always makes an unchanged recursive call.
+    /// Subexpressions are visited in the order they're evaluated in.
+    fn expr(&mut self, expr: &Expr) -> bool {
+        match *expr {
+            Expr::Literal(..)
+            | Expr::Bool(_)
+            | Expr::Float(_)
+            | Expr::Identifier(_)
+            | Expr::Str(_) => false,
+            Expr::TypeOf(_) | Expr::Break | Expr::Continue | Expr::Label(_) => false,
+            Expr::Goto(_) => {
+                // Jumping may skip the rest of the function, so nothing after
+                // this counts.
+                self.opaque = true;
+                false
+            }
+            Expr::Neg(ref expr) | Expr::Not(ref expr) | Expr::Cast(ref expr, _) => self.expr(expr),
+            Expr::GlobalDataAddr(ref name) => {
+                // Anything may be written through the address.
+                self.modified.insert(name.clone());
+                false
+            }
+            Expr::Assign(ref name, ref value) => {
+                let recurses = self.expr(value);
+                self.modified.insert(name.clone());
+                recurses
+            }
+            Expr::Div(ref lhs, ref rhs) | Expr::Mod(ref lhs, ref rhs) => {
+                // Division by zero traps, which would end the recursion.
+                self.expr(lhs) || self.expr(rhs) || {
+                    self.opaque = true;
+                    false
+                }
+            }
+            Expr::Deref(ref pointer) => {
+                // So might a bad memory access.
+                self.expr(pointer) || {
+                    self.opaque = true;
+                    false
+                }
+            }
+            Expr::Index(ref lhs, ref rhs) | Expr::DerefAssign(ref lhs, ref rhs) => {
+                self.expr(lhs) || self.expr(rhs) || {
+                    self.opaque = true;
+                    false
+                }
+            }
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                self.expr(array) || self.expr(index) || self.expr(value) || {
+                    self.opaque = true;
+                    false
+                }
+            }
+            Expr::Eq(ref lhs, ref rhs)
+            | Expr::Ne(ref lhs, ref rhs)
+            | Expr::Lt(ref lhs, ref rhs)
+            | Expr::Le(ref lhs, ref rhs)
+            | Expr::Gt(ref lhs, ref rhs)
+            | Expr::Ge(ref lhs, ref rhs)
+            | Expr::Add(ref lhs, ref rhs)
+            | Expr::Sub(ref lhs, ref rhs)
+            | Expr::Mul(ref lhs, ref rhs)
+            | Expr::BitAnd(ref lhs, ref rhs)
+            | Expr::BitOr(ref lhs, ref rhs)
+            | Expr::BitXor(ref lhs, ref rhs)
+            | Expr::Shl(ref lhs, ref rhs)
+            | Expr::Shr(ref lhs, ref rhs)
+            | Expr::UShr(ref lhs, ref rhs) => self.expr(lhs) || self.expr(rhs),
+            Expr::And(ref lhs, ref rhs) | Expr::Or(ref lhs, ref rhs) => {
+                // The right operand may not be evaluated at all.
+                self.expr(lhs) || {
+                    self.expr(rhs);
+                    false
+                }
+            }
+            Expr::IfElse(ref condition, ref then_body, ref else_body) => {
+                if self.expr(condition) {
+                    return true;
+                }
+                let (modified, opaque) = (self.modified.clone(), self.opaque);
+                let then_recurses = self.stmts(then_body);
+                let then_state = mem::replace(&mut self.modified, modified);
+                let then_opaque = mem::replace(&mut self.opaque, opaque);
+                let else_recurses = self.stmts(else_body);
+                self.modified.extend(then_state);
+                self.opaque |= then_opaque;
+                then_recurses && else_recurses
+            }
+            Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+                // The condition is evaluated at least once, but the body may
+                // not be, and a `break` skips the else-body.
+                self.expr(condition) || {
+                    self.stmts(loop_body);
+                    self.stmts(else_body);
+                    false
+                }
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                // The body always runs, but a `break` may leave it early.
+                self.stmts(loop_body);
+                self.expr(condition);
+                false
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                // The count may be zero.
+                self.expr(count) || {
+                    self.stmts(loop_body);
+                    false
+                }
+            }
+            Expr::Call(ref name, ref args) => {
+                if self.stmts(args) {
+                    return true;
+                }
+                if *name != self.function.name {
+                    self.opaque = true;
+                    return false;
+                }
+                !self.opaque
+                    && args.len() == self.function.params.len()
+                    && args
+                        .iter()
+                        .zip(&self.function.params)
+                        .all(|(arg, param)| match *arg {
+                            Expr::Identifier(ref name) => {
+                                name == param && !self.modified.contains(param)
+                            }
+                            _ => false,
+                        })
+            }
+        }
+    }
+}
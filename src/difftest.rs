@@ -0,0 +1,704 @@
+//! Differential testing: run a corpus of toy programs both through the JIT
+//! and through the interpreter, and check that they agree.
+//!
+//! Since the interpreter and the translator share nothing but the AST, a
+//! disagreement almost always points at a bug in one of them. The corpus is
+//! compiled with sealing checked, too.
+
+use jit::JIT;
+
+/// Each program in the corpus, with the argument lists to run it with.
+/// Functions may call, or inline, any function defined earlier in the corpus.
+const CORPUS: &[(&str, &[&[isize]])] = &[
+    (
+        "fn add_mul(a, b) -> (r) {
+            r = a + b * 2
+        }
+        ",
+        &[&[0, 0], &[1, 2], &[-7, 3], &[100, -100]],
+    ),
+    (
+        "fn sub_chain(a, b, c) -> (r) {
+            r = a - b - c
+        }
+        ",
+        &[&[10, 3, 2], &[0, 0, 0], &[-5, 7, 11]],
+    ),
+    (
+        "fn quotient(a, b) -> (r) {
+            r = a / b
+        }
+        ",
+        &[&[7, 2], &[100, 10], &[1, 3], &[-4, 2]],
+    ),
+    (
+        "fn compare(a, b) -> (r) {
+            lt = a < b
+            le = a <= b
+            gt = a > b
+            ge = a >= b
+            eq = a == b
+            ne = a != b
+            r = lt + le * 2 + gt * 4 + ge * 8 + eq * 16 + ne * 32
+        }
+        ",
+        &[&[1, 2], &[2, 1], &[3, 3], &[-1, 1]],
+    ),
+    (
+        "fn max(a, b) -> (r) {
+            r = if a > b {
+                a
+            } else {
+                b
+            }
+        }
+        ",
+        &[&[1, 2], &[2, 1], &[-3, -4]],
+    ),
+    (
+        "fn sign(n) -> (r) {
+            r = n < 0 ? 0 - 1 : n == 0 ? 0 : 1
+        }
+        ",
+        &[&[-9], &[0], &[9]],
+    ),
+    (
+        "fn abs(n) -> (r) {
+            r = n
+            if n < 0 {
+                r = 0 - n
+            } else {
+            }
+        }
+        ",
+        &[&[-9], &[0], &[9]],
+    ),
+    (
+        "fn triangle(n) -> (r) {
+            r = 0
+            while n > 0 {
+                r = r + n
+                n = n - 1
+            }
+        }
+        ",
+        &[&[0], &[1], &[10], &[100]],
+    ),
+    (
+        "fn factorial(n) -> (r) {
+            r = 1
+            while n > 1 {
+                r = r * n
+                n = n - 1
+            }
+        }
+        ",
+        &[&[0], &[1], &[5], &[20]],
+    ),
+    (
+        "fn gcd(a, b) -> (r) {
+            while a != b {
+                if a > b {
+                    a = a - b
+                } else {
+                    b = b - a
+                }
+            }
+            r = a
+        }
+        ",
+        &[&[12, 18], &[7, 13], &[100, 75]],
+    ),
+    (
+        "fn fib(n) -> (r) {
+            r = n < 2 ? n : fib(n - 1) + fib(n - 2)
+        }
+        ",
+        &[&[0], &[1], &[2], &[12]],
+    ),
+    (
+        "fn square(x) -> (r) {
+            r = x * x
+        }
+        ",
+        &[&[0], &[-3], &[12]],
+    ),
+    (
+        "fn sum_squares(a, b) -> (r) {
+            r = square(a) + square(b)
+        }
+        ",
+        &[&[3, 4], &[-5, 12]],
+    ),
+    (
+        "fn piped(x) -> (r) {
+            r = x |> square |> fib
+        }
+        ",
+        &[&[0], &[2], &[3]],
+    ),
+    (
+        "fn pairs(n) -> (r) {
+            r = 0
+            i = 0
+            while i < n {
+                j = i
+                while j < n {
+                    r = r + 1
+                    j = j + 1
+                }
+                i = i + 1
+            }
+        }
+        ",
+        &[&[0], &[1], &[4], &[10]],
+    ),
+    (
+        "fn collatz(n) -> (r) {
+            r = 0
+            while n != 1 {
+                half = n / 2
+                n = if n == half + half {
+                    half
+                } else {
+                    n * 3 + 1
+                }
+                r = r + 1
+            }
+        }
+        ",
+        &[&[1], &[6], &[27]],
+    ),
+    (
+        "fn power(b, e) -> (r) {
+            r = 1
+            while e > 0 {
+                r = r * b
+                e = e - 1
+            }
+        }
+        ",
+        &[&[2, 10], &[3, 0], &[-2, 5]],
+    ),
+    (
+        "#[inline]
+        fn count_up(n) -> (r) {
+            while r < n {
+                r = r + 1
+            }
+        }
+        ",
+        &[&[0], &[5]],
+    ),
+    (
+        "fn inlined_in_loop(n) -> (r) {
+            i = 0
+            while i < n {
+                r = r + count_up(i)
+                i = i + 1
+            }
+        }
+        ",
+        &[&[0], &[1], &[6]],
+    ),
+    (
+        "fn type_tag(x) -> (r) {
+            r = typeof(x + 1) * 10 + typeof(square(x))
+        }
+        ",
+        &[&[0], &[3]],
+    ),
+    (
+        "fn digits(a, b) -> (r) {
+            r = a * 10 + b
+        }
+        ",
+        &[&[1, 2]],
+    ),
+    (
+        "fn arg_order(x) -> (r) {
+            r = digits(x = x + 1, x = x * 3)
+        }
+        ",
+        &[&[1], &[2]],
+    ),
+    (
+        "fn sum_point(p: { x, y }) -> (r) {
+            p.x = p.x * 10
+            r = p.x + p.y
+        }
+        ",
+        &[&[1, 2], &[-3, 4]],
+    ),
+    (
+        "fn use_point(a) -> (r) {
+            r = sum_point({ x: a, y: a - 1 })
+        }
+        ",
+        &[&[0], &[7]],
+    ),
+    (
+        "fn swap_gcd(a, b) -> (r) {
+            while b != 0 {
+                (a, b) = (b, a % b)
+            }
+            r = a
+        }
+        ",
+        &[&[12, 18], &[7, 0], &[-4, 6]],
+    ),
+    (
+        "fn clamped(x) -> (r) {
+            r = x
+            if r > 10 {
+                r = 10
+            }
+            if r < 0 {
+                r = 0
+            }
+        }
+        ",
+        &[&[-3], &[4], &[12]],
+    ),
+    (
+        "fn grade(score) -> (r) {
+            if score >= 90 {
+                r = 4
+            } else if score >= 80 {
+                r = 3
+            } else if score >= 70 {
+                r = 2
+            }
+        }
+        ",
+        &[&[95], &[85], &[75], &[10]],
+    ),
+    (
+        "fn skip_threes(n) -> (r) {
+            i = 0
+            while i < n {
+                i = i + 1
+                if i % 3 == 0 {
+                    continue
+                }
+                r = r + i
+            }
+            repeat n {
+                n = n - 1
+                if n % 2 == 0 {
+                    continue
+                }
+                r = r + 100
+            }
+        }
+        ",
+        &[&[0], &[7], &[-2]],
+    ),
+    (
+        "fn triangle_for(n) -> (r) {
+            i = 100
+            for i in 0 .. n + 1 {
+                if i == 3 {
+                    continue
+                }
+                r = r + i
+                i = i * 2
+            }
+            r = r + i
+        }
+        ",
+        &[&[0], &[5], &[-1]],
+    ),
+    (
+        "fn collatz_steps(n) -> (r) {
+            do {
+                if n <= 1 {
+                    break
+                }
+                r = r + 1
+                if n % 2 == 0 {
+                    n = n / 2
+                    continue
+                }
+                n = 3 * n + 1
+            } while r < 100
+        }
+        ",
+        &[&[1], &[6], &[27], &[-5]],
+    ),
+    (
+        "fn let_sums(n) -> (r) {
+            total = 0
+            while n > 0 {
+                let total = n * 2
+                r = r + total
+                n = n - 1
+            }
+            r = r + total
+        }
+        ",
+        &[&[0], &[4]],
+    ),
+    (
+        "fn self_checked(n) -> (r) {
+            r = triangle(n)
+            assert_eq(r + r, n * n + n)
+        }
+        ",
+        &[&[0], &[9]],
+    ),
+    (
+        "fn saturating_add(a, b) -> (r) {
+            r = a + b
+            if a > 0 {
+                if r < b {
+                    r = INT_MAX
+                } else {
+                }
+            } else {
+            }
+        }
+        ",
+        &[&[1, 2], &[1 << 62, 1 << 62], &[-5, isize::MAX]],
+    ),
+    (
+        "fn root(x) -> (r) {
+            r = isqrt(x)
+        }
+        ",
+        &[
+            &[0],
+            &[1],
+            &[2],
+            &[15],
+            &[16],
+            &[17],
+            &[1_000_000],
+            &[isize::MAX],
+        ],
+    ),
+    (
+        "fn radixes() -> (r) {
+            r = 0o17 * 1000000 + 0b101 * 1000 + 0x1F + 0xa
+        }
+        ",
+        &[&[]],
+    ),
+    (
+        "fn logic(a, b, c) -> (r) {
+            r = a && b || c
+            r = r * 10 + a || b && c
+        }
+        ",
+        &[
+            &[0, 0, 0],
+            &[0, 0, 5],
+            &[0, 3, 5],
+            &[2, 3, 0],
+            &[2, 0, 0],
+            &[-1, 0, 1],
+        ],
+    ),
+    (
+        "fn skipped(n) -> (r) {
+            r = n != 0 && 100 / n > 5
+        }
+        ",
+        &[&[0], &[10], &[20]],
+    ),
+    (
+        "fn negatives(a, b) -> (r) {
+            r = -a * -b - -a
+        }
+        ",
+        &[&[0, 0], &[3, 4], &[-3, 4], &[isize::MIN, 1]],
+    ),
+    (
+        "fn first_multiple(n, k) -> (r) {
+            r = n
+            while r > 0 {
+                q = r / k
+                if q * k == r {
+                    break
+                } else {
+                }
+                r = r - 1
+            } else {
+                r = 0 - 1
+            }
+        }
+        ",
+        &[&[0, 3], &[10, 3], &[2, 3], &[9, 9]],
+    ),
+    (
+        "fn inner_breaks(n) -> (r) {
+            i = 0
+            while i < n {
+                j = 0
+                while 1 {
+                    if j == i {
+                        break
+                    } else {
+                    }
+                    r = r + 1
+                    j = j + 1
+                }
+                i = i + 1
+            } else {
+                r = r * 10
+            }
+        }
+        ",
+        &[&[0], &[1], &[4]],
+    ),
+    (
+        "fn bucket(a, b) -> (r) {
+            r = cond { a == b => 0, a < b => 1, _ => 2 }
+            r = r * 10 + cond {
+                _ => a
+            }
+        }
+        ",
+        &[&[1, 1], &[1, 2], &[3, 2]],
+    ),
+    (
+        "fn skip_ahead(n) -> (r) {
+            r = 1
+            if n > 5 {
+                goto done
+            } else {
+            }
+            r = r + n
+            while n > 0 {
+                n = n - 1
+                if n == 2 {
+                    goto done
+                } else {
+                }
+                r = r * 2
+            }
+        done:
+            r = r + 100
+        }
+        ",
+        &[&[0], &[1], &[4], &[9]],
+    ),
+    (
+        "fn repeats(n) -> (r) {
+            repeat 2 + 2 {
+                r = r * 2 + 1
+            }
+            repeat n {
+                r = r + 1
+                if r > 30 {
+                    break
+                } else {
+                }
+            }
+            repeat INT_MIN {
+                r = 0
+            }
+        }
+        ",
+        &[&[0], &[5], &[-5], &[100]],
+    ),
+    (
+        "fn named(a, b) -> (r) {
+            r = digits(b = a - b, a = b)
+            r = r + digits(a = a, 7)
+        }
+        ",
+        &[&[1, 2], &[5, 3]],
+    ),
+    (
+        "fn defaulted(a, b = 0 - 1, c = INT_MAX) -> (r) {
+            r = a + b * 10 + c
+        }
+        ",
+        &[&[1, 2, 3], &[0, 0, -1]],
+    ),
+    (
+        "fn use_defaulted(x) -> (r) {
+            r = defaulted(x) + defaulted(x, x) + defaulted(c = 1, a = x)
+        }
+        ",
+        &[&[0], &[4]],
+    ),
+    (
+        "fn remainder(a, b) -> (r) {
+            r = a % b * 100 + a / b % 10
+        }
+        ",
+        &[&[7, 2], &[100, 7], &[5, 9], &[-1, 3]],
+    ),
+    (
+        "fn bits(a, b) -> (r) {
+            both = a & b
+            either = a | b
+            one = a ^ b
+            r = both + either * 3 + one * 7 + a & 1 ^ b | 2
+        }
+        ",
+        &[&[0, 0], &[12, 10], &[-1, 5], &[isize::MIN, -1]],
+    ),
+    (
+        "fn shifts(a, n) -> (r) {
+            left = a << n
+            right = a >> n
+            logical = a >>> n
+            r = left ^ right * 3 ^ logical * 5 + 1 << n & 7
+        }
+        ",
+        &[&[1, 0], &[5, 3], &[-5, 3], &[-1, 63], &[3, 64], &[7, -1]],
+    ),
+    (
+        "fn negated(a, b) -> (r) {
+            r = !a * 10 + !!b
+            done = b == 0
+            while !done {
+                b = b - 1
+                r = r + 100
+                done = b == 0
+            }
+        }
+        ",
+        &[&[0, 0], &[0, 3], &[-4, 1], &[isize::MIN, 2]],
+    ),
+    (
+        "fn grouped(a, b, c) -> (r) {
+            r = (a - b) * (c + 1) - a / (b - c) - (c - (a % 7)) << 2
+            r = r - (r > 100 || (a == b) != (b == c)) * (r - 2 - a)
+        }
+        ",
+        &[&[1, 2, 3], &[30, 4, 2], &[-8, 5, 1]],
+    ),
+    (
+        "fn signed_literals(a) -> (r) {
+            r = a * -3 - -2147483648
+            r = r + (a < -0x80000000) + (-0b1 == a) * 1000
+        }
+        ",
+        &[&[0], &[-1], &[7]],
+    ),
+    (
+        "fn wide_literals(a) -> (r) {
+            r = a * 3000000000 + 0xFFFFFFFF - (a < -0x100000000)
+        }
+        ",
+        &[&[0], &[2], &[-5000000000]],
+    ),
+    (
+        "// Comments are whitespace.
+        fn commented(a) -> (r) { // even here
+
+            // A whole line.
+            r = a // the end of a line
+            r = r / 2 // this isn't a third operand / 4
+            r = r /* inline */ + 1 /* with /* nested */ comments */ * 3
+            /*
+            r = 0
+            /* r = 1 */
+            r = 2
+            */
+
+        }
+        // After the last function.
+        ",
+        &[&[0], &[9]],
+    ),
+    (
+        "fn booleans(a, b) -> (r) {
+            r = true * 10 + false + typeof(true)
+            if a < b && true {
+                r = r + 100
+            } else {
+            }
+            while !(a >= b) || false {
+                a = a + 1
+                r = r + 1
+            }
+            r = r + (a == b || !true) * 1000 + (a != b) * 10000
+        }
+        ",
+        &[&[0, 0], &[1, 4], &[9, 2]],
+    ),
+    (
+        "fn lengths() -> (r) {
+            r = len(\"hello\") * 10 + len(\"a\\nb\")
+        }
+        ",
+        &[&[]],
+    ),
+    (
+        "fn folded(n) -> (r) {
+            while 0 {
+                r = 100
+            } else {
+                r = n
+            }
+            while 1 {
+                if INT_MIN {
+                    r = r + 1
+                } else {
+                    break
+                }
+                if r > 3 {
+                    break
+                } else {
+                }
+            }
+            r = if 2 - 2 {
+                r
+            } else {
+                r * 10
+            }
+        }
+        ",
+        &[&[0], &[7]],
+    ),
+    (
+        "operator <+> (a, b) -> (r) {
+            r = a * 10 + b
+        }
+        ",
+        &[&[1, 2], &[-3, 4]],
+    ),
+    (
+        "fn use_operator(x) -> (r) {
+            r = x <+> x + 1 <+> 3
+            r = r <+> 0 < 1 ? 5 : 6
+        }
+        ",
+        &[&[0], &[2]],
+    ),
+    (
+        "fn nothing() -> (r) {
+        }
+        ",
+        &[&[]],
+    ),
+];
+
+/// Compile and interpret every program in the corpus with each of its
+/// argument lists, returning the number of runs which agreed, or a
+/// description of the first disagreement.
+pub fn run_corpus() -> Result<usize, String> {
+    let mut jit = JIT::new();
+    jit.set_check_sealing(true);
+    let mut runs = 0;
+    for &(code, inputs) in CORPUS {
+        let func = jit.compile_dynamic(code)?;
+        for args in inputs {
+            let interpreted = jit.interpret(code, args)?;
+            let compiled = func.call(args)?;
+            if interpreted != compiled {
+                return Err(format!(
+                    "interpreted {} but compiled {} with arguments {:?} for:\n{}",
+                    interpreted, compiled, args, code
+                ));
+            }
+            runs += 1;
+        }
+    }
+    Ok(runs)
+}
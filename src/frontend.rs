@@ -1,6 +1,17 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
 /// The AST node for expressions.
+#[derive(Clone)]
 pub enum Expr {
-    Literal(String),
+    /// An integer literal's digits, preceded by a `-` if it's negative but
+    /// without any prefix, and its radix.
+    Literal(String, u32),
+    /// `true` or `false`, which are 1 and 0.
+    Bool(bool),
+    /// A floating-point literal, such as `1.5` or `-2.0e3`, which is an
+    /// `f64`.
+    Float(String),
     Identifier(String),
     Assign(String, Box<Expr>),
     Eq(Box<Expr>, Box<Expr>),
@@ -12,14 +23,768 @@ pub enum Expr {
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Box<Expr>, Box<Expr>),
+    /// `a / b`, which rounds towards zero.
     Div(Box<Expr>, Box<Expr>),
+    /// `a % b`, the remainder of `a / b`, which has the sign of `a`.
+    Mod(Box<Expr>, Box<Expr>),
+    /// `a & b`, a bitwise and.
+    BitAnd(Box<Expr>, Box<Expr>),
+    /// `a | b`, a bitwise or.
+    BitOr(Box<Expr>, Box<Expr>),
+    /// `a ^ b`, a bitwise exclusive or.
+    BitXor(Box<Expr>, Box<Expr>),
+    /// `a << b`, shifting `a` left by `b` bits.
+    Shl(Box<Expr>, Box<Expr>),
+    /// `a >> b`, an arithmetic shift right, which copies the sign bit.
+    Shr(Box<Expr>, Box<Expr>),
+    /// `a >>> b`, a logical shift right, which shifts in zeros.
+    UShr(Box<Expr>, Box<Expr>),
+    /// `-a`.
+    Neg(Box<Expr>),
+    /// `!a`, which is 1 if `a` is zero, and 0 otherwise.
+    Not(Box<Expr>),
+    /// `*p`, the integer at the address `p`, as `load(p, 0)`.
+    Deref(Box<Expr>),
+    /// `*p = x`, which writes `x` to the address `p`, as `store(p, 0, x)`.
+    DerefAssign(Box<Expr>, Box<Expr>),
+    /// `a[i]`, the `i`th integer from the address `a`, as `load(a, i)`.
+    Index(Box<Expr>, Box<Expr>),
+    /// `a[i] = x`, which writes `x` as the `i`th integer from the address
+    /// `a`, as `store(a, i, x)`.
+    IndexAssign(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `a as f64`, which converts `a` to another type, just as the builtin
+    /// conversion by that name does.
+    Cast(Box<Expr>, ValueType),
+    /// `a && b`, which only evaluates `b` if `a` is nonzero.
+    And(Box<Expr>, Box<Expr>),
+    /// `a || b`, which only evaluates `b` if `a` is zero.
+    Or(Box<Expr>, Box<Expr>),
+    /// `if c { then_body } else { else_body }`. Without an `else`, the
+    /// else-body is empty, so the value is zero when `c` is, and with an
+    /// `else if`, it's the next if-else of the chain.
     IfElse(Box<Expr>, Vec<Expr>, Vec<Expr>),
-    WhileLoop(Box<Expr>, Vec<Expr>),
+    /// `while c { body } else { else_body }`, where the else-body runs when
+    /// the condition becomes false, but not when the loop is left through a
+    /// `break`. Without an `else`, the else-body is empty.
+    WhileLoop(Box<Expr>, Vec<Expr>, Vec<Expr>),
+    /// `do { body } while c`, which runs the body, and then runs it again
+    /// for as long as the condition is true. A `continue` goes on to the
+    /// condition.
+    DoWhile(Vec<Expr>, Box<Expr>),
+    /// `repeat n { body }`, which runs the body `n` times, evaluating `n`
+    /// once beforehand.
+    Repeat(Box<Expr>, Vec<Expr>),
+    /// Leave the innermost enclosing loop.
+    Break,
+    /// Skip the rest of the body of the innermost enclosing loop, going on to
+    /// test its condition again, or to its next iteration.
+    Continue,
+    /// `name:`, marking a place a `goto` can jump to. Labels may only appear
+    /// among a function's top-level statements.
+    Label(String),
+    /// `goto name`, jumping to the label `name` in the same function.
+    Goto(String),
     Call(String, Vec<Expr>),
+    /// `&name`, the address of the variable `name`, if the function has one
+    /// by that name, and otherwise of the data object `name`. Variables
+    /// whose address is taken must be integers, and are kept on the stack
+    /// rather than in registers.
     GlobalDataAddr(String),
+    /// A string literal, with its escapes decoded. Its value is the address
+    /// of its bytes, which are followed by a NUL, and last as long as the JIT.
+    Str(String),
+    TypeOf(Box<Expr>),
+}
+
+/// A function built into the language, which the translator and the
+/// interpreter handle themselves, rather than calling a function by that name.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Builtin {
+    /// `assert_eq(a, b)`, which traps unless `a` and `b` are equal.
+    AssertEq,
+    /// `isqrt(x)`, the integer square root of `x`.
+    Isqrt,
+    /// `len("...")`, the length in bytes of a string literal, which is known
+    /// without evaluating anything.
+    Len,
+    /// `f32(x)`, `x` converted to an `f32` from an integer or an `f64`.
+    F32,
+    /// `f64(x)`, `x` converted to an `f64` from an integer or an `f32`.
+    F64,
+    /// `i32(x)`, `x` converted to an `i32`, by truncating an integer, or
+    /// rounding a float towards zero.
+    I32,
+    /// `i64(x)`, `x` converted to a 64-bit integer, by sign-extending an
+    /// `i32`, or rounding a float towards zero.
+    I64,
+    /// `array(n)`, the address of `n` integers on the stack, which start out
+    /// as zero.
+    Array,
+    /// `load(a, i)`, the `i`th integer from the address `a`.
+    Load,
+    /// `store(a, i, x)`, which writes `x` as the `i`th integer from the
+    /// address `a`, and evaluates to `x`.
+    Store,
+    /// `alloc(n)`, the address of `n` integers from the host's allocator,
+    /// which start out as zero.
+    Alloc,
+    /// `free(p)`, which gives the integers `alloc` allocated at `p` back to
+    /// the host's allocator, and evaluates to zero.
+    Free,
+}
+
+/// Every builtin, by the name it's called with.
+pub const BUILTINS: &[(&str, Builtin)] = &[
+    ("assert_eq", Builtin::AssertEq),
+    ("isqrt", Builtin::Isqrt),
+    ("len", Builtin::Len),
+    ("f32", Builtin::F32),
+    ("f64", Builtin::F64),
+    ("i32", Builtin::I32),
+    ("i64", Builtin::I64),
+    ("array", Builtin::Array),
+    ("load", Builtin::Load),
+    ("store", Builtin::Store),
+    ("alloc", Builtin::Alloc),
+    ("free", Builtin::Free),
+];
+
+/// The length of the string literal `len` is given, which must be its only
+/// argument.
+pub fn string_len(args: &[Expr]) -> Result<usize, String> {
+    match *args {
+        [Expr::Str(ref contents)] => Ok(contents.len()),
+        [_] => Err("`len` takes a string literal".to_string()),
+        _ => Err(format!(
+            "`len` takes 1 argument but {} were given",
+            args.len()
+        )),
+    }
+}
+
+/// `count` arguments, as error messages about calls say it.
+pub fn arguments(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        _ => format!("{} arguments", count),
+    }
+}
+
+impl Builtin {
+    /// The builtin called `name`, if there is one.
+    pub fn lookup(name: &str) -> Option<Builtin> {
+        BUILTINS
+            .iter()
+            .find(|builtin| builtin.0 == name)
+            .map(|builtin| builtin.1)
+    }
+}
+
+/// The tag `typeof` produces for `i32`s.
+pub const TYPEOF_I32: i64 = 0;
+
+/// The tag `typeof` produces for integers.
+pub const TYPEOF_INT: i64 = 1;
+
+/// The tag `typeof` produces for `f64`s.
+pub const TYPEOF_F64: i64 = 2;
+
+/// The tag `typeof` produces for bools.
+pub const TYPEOF_BOOL: i64 = 3;
+
+/// The tag `typeof` produces for pointers.
+pub const TYPEOF_PTR: i64 = 4;
+
+/// The tag `typeof` produces for `f32`s.
+pub const TYPEOF_F32: i64 = 5;
+
+/// The tag `typeof` produces for `u32`s.
+pub const TYPEOF_U32: i64 = 6;
+
+/// The tag `typeof` produces for `u64`s.
+pub const TYPEOF_U64: i64 = 7;
+
+/// The types of values in the toy language. Every value is an integer unless
+/// it's known to be something else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    /// A pointer-sized integer, which is 64 bits on the hosts Cranelift
+    /// supports.
+    Int,
+    /// A 32-bit integer. When it's mixed with an `Int`, it's sign-extended.
+    I32,
+    /// An unsigned 32-bit integer, which is zero-extended when it's mixed
+    /// with a wider integer.
+    U32,
+    /// An unsigned 64-bit integer.
+    U64,
+    /// The address of an integer, which is a pointer-sized integer itself.
+    /// Adding `n` to it moves it on by `n` integers, rather than `n` bytes.
+    Ptr,
+    /// `true` or `false`, which are the pointer-sized integers 1 and 0, and
+    /// are treated as integers by arithmetic.
+    Bool,
+    F32,
+    F64,
+}
+
+impl ValueType {
+    /// The tag `typeof` produces for values of this type.
+    pub fn tag(self) -> i64 {
+        match self {
+            ValueType::Int => TYPEOF_INT,
+            ValueType::I32 => TYPEOF_I32,
+            ValueType::U32 => TYPEOF_U32,
+            ValueType::U64 => TYPEOF_U64,
+            ValueType::Ptr => TYPEOF_PTR,
+            ValueType::Bool => TYPEOF_BOOL,
+            ValueType::F32 => TYPEOF_F32,
+            ValueType::F64 => TYPEOF_F64,
+        }
+    }
+
+    /// Whether this is an unsigned integer type, whose comparisons and
+    /// division are unsigned.
+    pub fn is_unsigned(self) -> bool {
+        self == ValueType::U32 || self == ValueType::U64
+    }
+}
+
+impl fmt::Display for ValueType {
+    /// The type's name, as it's written in a type annotation or a cast.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ValueType::Int => "i64",
+            ValueType::I32 => "i32",
+            ValueType::U32 => "u32",
+            ValueType::U64 => "u64",
+            ValueType::Ptr => "ptr",
+            ValueType::Bool => "bool",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The AST node for function definitions.
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub the_return: String,
+    pub stmts: Vec<Expr>,
+    pub inline: Inline,
+    /// The default value of each parameter, written `b = 10`, if it has one.
+    /// Only trailing parameters may have defaults, and they must be constant.
+    pub defaults: Vec<Option<Expr>>,
+    /// The types declared for the parameters and the return variable, written
+    /// `a: f64`. Other variables take the type of what's assigned to them.
+    pub types: HashMap<String, ValueType>,
+}
+
+impl Function {
+    /// Match the arguments of a call to this function to its parameters.
+    ///
+    /// If every argument is written `name = value`, and at least one of the
+    /// names is a parameter, the arguments are passed by name, and their
+    /// values are returned in the order of the parameters, which is the order
+    /// they're evaluated in. Otherwise, each `name = value` is an assignment,
+    /// whose value is passed by position like any other argument.
+    ///
+    /// Either way, parameters which aren't given take their default values.
+    pub fn bind_arguments(&self, args: Vec<Expr>) -> Result<Vec<Expr>, String> {
+        let is_param = |arg: &Expr| match *arg {
+            Expr::Assign(ref name, _) => self.params.contains(name),
+            _ => false,
+        };
+        let by_name =
+            args.iter().all(|arg| matches!(*arg, Expr::Assign(..))) && args.iter().any(is_param);
+        if !by_name {
+            // Only fill in defaults if that gives the right number of
+            // arguments, and leave reporting the mismatch to the caller
+            // otherwise.
+            let missing = &self.defaults[args.len().min(self.defaults.len())..];
+            if missing.iter().any(Option::is_none) {
+                return Ok(args);
+            }
+            let mut args = args;
+            args.extend(missing.iter().cloned().map(Option::unwrap));
+            return Ok(args);
+        }
+
+        let mut values = vec![None; self.params.len()];
+        for arg in args {
+            if let Expr::Assign(name, value) = arg {
+                match self.params.iter().position(|param| *param == name) {
+                    None => return Err(format!("`{}` has no parameter `{}`", self.name, name)),
+                    Some(index) if values[index].is_some() => {
+                        return Err(format!(
+                            "parameter `{}` of `{}` is given more than once",
+                            name, self.name
+                        ))
+                    }
+                    Some(index) => values[index] = Some(*value),
+                }
+            }
+        }
+        values
+            .into_iter()
+            .zip(&self.params)
+            .zip(&self.defaults)
+            .map(|((value, param), default)| {
+                value
+                    .or_else(|| default.clone())
+                    .ok_or_else(|| format!("parameter `{}` of `{}` is not given", param, self.name))
+            })
+            .collect()
+    }
+}
+
+/// The AST node for the top-level items of a program.
+pub enum Item {
+    Function(Function),
+    /// `alias bar = foo` makes `bar` another name for the function `foo`.
+    Alias(String, String),
+    /// `const WIDTH = 640` makes `WIDTH` a constant, with the value of an
+    /// expression which can be computed without running the program.
+    Const(String, Expr),
+}
+
+/// A struct, an enum or a global, which a program's functions can use
+/// wherever in it it's defined.
+pub enum Definition {
+    /// `struct Point { x, y }`, with the names of its fields.
+    Struct(String, Vec<String>),
+    /// `enum Color { Red, Green, Blue }`, with the names of its variants.
+    Enum(String, Vec<String>),
+    /// `global counter = 0`, with its initial value.
+    Global(String, i64),
+}
+
+/// The structs, enums and globals which functions can use, by name. The
+/// names of structs and enums are apart from those of variables and
+/// functions.
+#[derive(Clone, Default)]
+pub struct Definitions {
+    /// The fields of each struct, in order.
+    pub structs: HashMap<String, Vec<String>>,
+    /// The variants of each enum, in order.
+    pub enums: HashMap<String, Vec<String>>,
+    /// The global variables, each of which is a data object holding an
+    /// integer.
+    pub globals: HashSet<String>,
+}
+
+/// Whether calls to a function should be inlined, as requested by an
+/// `#[inline]` or `#[noinline]` attribute on its definition.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Inline {
+    /// No attribute; calls are left as calls.
+    Default,
+    /// `#[inline]`: calls are replaced by the function's body.
+    Always,
+    /// `#[noinline]`: calls are never replaced by the function's body.
+    Never,
+}
+
+/// The name of the function defined with `operator op (a, b) -> (r)`, which
+/// uses of `a op b` call. It can't be written as an identifier, so it never
+/// clashes with a function defined with `fn`.
+pub fn operator_function(op: &str) -> String {
+    format!("operator {}", op)
+}
+
+/// The fields given in the literal of the struct `name`, in the order the
+/// struct defines them, with zero for those which aren't given.
+pub fn struct_fields(
+    structs: &HashMap<String, Vec<String>>,
+    name: &str,
+    fields: Vec<(String, Expr)>,
+) -> Result<Vec<(String, Expr)>, &'static str> {
+    let defined = structs.get(name).ok_or("a struct")?;
+    let mut values: Vec<Option<Expr>> = vec![None; defined.len()];
+    for (field, value) in fields {
+        match defined.iter().position(|f| *f == field) {
+            Some(index) if values[index].is_none() => values[index] = Some(value),
+            _ => return Err("each field of the struct at most once"),
+        }
+    }
+    Ok(defined
+        .iter()
+        .cloned()
+        .zip(values)
+        .map(|(field, value)| {
+            (
+                field,
+                value.unwrap_or_else(|| Expr::Literal("0".to_string(), 10)),
+            )
+        })
+        .collect())
+}
+
+/// Reading the variable `name`, which is `*&name` if it's a global.
+pub fn read_variable(definitions: &Definitions, name: String) -> Expr {
+    if definitions.globals.contains(&name) {
+        Expr::Deref(Box::new(Expr::GlobalDataAddr(name)))
+    } else {
+        Expr::Identifier(name)
+    }
+}
+
+/// Assigning `value` to the variable `name`, which is `*&name = value` if
+/// it's a global.
+pub fn assign_variable(definitions: &Definitions, name: String, value: Expr) -> Expr {
+    if definitions.globals.contains(&name) {
+        Expr::DerefAssign(Box::new(Expr::GlobalDataAddr(name)), Box::new(value))
+    } else {
+        Expr::Assign(name, Box::new(value))
+    }
+}
+
+/// The statements the assignment `(a, b) = (x, y)` at the byte offset `pos`
+/// is lowered to. Every value is evaluated into a variable of its own before
+/// any of the targets is assigned, so `(a, b) = (b, a)` swaps them. Those
+/// variables can't be written in the toy language, and are named after
+/// where the assignment is, so they don't clash with any others.
+pub fn destructure(
+    definitions: &Definitions,
+    pos: usize,
+    targets: Vec<String>,
+    values: Vec<Expr>,
+) -> Vec<Expr> {
+    let temporaries: Vec<String> = (0..values.len())
+        .map(|i| format!("#tuple{}.{}", pos, i))
+        .collect();
+    let mut stmts: Vec<Expr> = temporaries
+        .iter()
+        .cloned()
+        .zip(values)
+        .map(|(temporary, value)| Expr::Assign(temporary, Box::new(value)))
+        .collect();
+    stmts.extend(
+        targets
+            .into_iter()
+            .zip(temporaries)
+            .map(|(target, temporary)| {
+                assign_variable(definitions, target, Expr::Identifier(temporary))
+            }),
+    );
+    stmts
+}
+
+/// The variable which `let name = ...` at the byte offset `pos` binds. It
+/// can't be written in the toy language, so it's apart from every other
+/// variable, including any others named `name`.
+pub fn let_variable(name: &str, pos: usize) -> String {
+    format!("{}#{}", name, pos)
+}
+
+/// The name `variable` is written as in the source, which for a variable
+/// `let_variable` gave is the name the `let` bound.
+pub fn source_name(variable: &str) -> &str {
+    match variable.find('#') {
+        Some(end) if end > 0 => &variable[..end],
+        _ => variable,
+    }
+}
+
+/// The statements of a block, in which each `let` binds its name from the
+/// statement after it to the end of the block, including the blocks nested
+/// in it, whose own `let`s have already been scoped. Each statement comes
+/// with the name it binds, if it's a `let`, whose statement assigns the
+/// variable `let_variable` gives. Every use of the name in its scope becomes
+/// a use of that variable, so that the translator, the interpreter and the
+/// analyses only ever see ordinary variables.
+///
+/// A `let` shadows any other binding of its name, whether by an earlier
+/// `let` or by a plain assignment somewhere else in the function, which is
+/// left alone and can be used again once the scope ends. The value of `let
+/// x = x + 1` uses the `x` from before it. The last `let` is scoped first,
+/// so that its uses are already renamed by the time an earlier `let` of the
+/// same name is.
+pub fn scope_lets(stmts: Vec<(Option<String>, Expr)>) -> Vec<Expr> {
+    let (names, mut stmts): (Vec<_>, Vec<_>) = stmts.into_iter().unzip();
+    for (i, name) in names.into_iter().enumerate().rev() {
+        let name = match name {
+            Some(name) => name,
+            None => continue,
+        };
+        let variable = match stmts[i] {
+            Expr::Assign(ref variable, _) => variable.clone(),
+            _ => unreachable!("a `let` is always an assignment"),
+        };
+        for stmt in &mut stmts[i + 1..] {
+            rename(stmt, &name, &variable);
+        }
+    }
+    stmts
+}
+
+/// The statements `for i in start .. end { body }` at the byte offset `pos`
+/// is lowered to: a while loop over a counter which goes from `start` up to,
+/// but not including, `end`, both of which are evaluated once beforehand.
+/// The counter is named like the variables of `destructure`. At the top of
+/// each iteration, `i` is bound to it as if by a `let`, so changing `i`
+/// doesn't change how many times the loop runs, and a `continue` still goes
+/// on to the next value.
+pub fn for_loop(
+    pos: usize,
+    name: String,
+    start: Expr,
+    end: Expr,
+    mut body: Vec<Expr>,
+) -> Vec<Expr> {
+    let next = format!("#for{}.next", pos);
+    let last = format!("#for{}.end", pos);
+    let variable = let_variable(&name, pos);
+    for stmt in &mut body {
+        rename(stmt, &name, &variable);
+    }
+    let read = |name: &str| Box::new(Expr::Identifier(name.to_string()));
+    let one = Box::new(Expr::Literal("1".to_string(), 10));
+    let mut loop_body = vec![
+        Expr::Assign(variable, read(&next)),
+        Expr::Assign(next.clone(), Box::new(Expr::Add(read(&next), one))),
+    ];
+    loop_body.extend(body);
+    vec![
+        Expr::Assign(next.clone(), Box::new(start)),
+        Expr::Assign(last.clone(), Box::new(end)),
+        Expr::WhileLoop(
+            Box::new(Expr::Lt(read(&next), read(&last))),
+            loop_body,
+            Vec::new(),
+        ),
+    ]
+}
+
+/// Whether storing `value` in `variable` is something the compiler wrote,
+/// rather than the source: a store to one of its own variables, whose names
+/// start with `#`, or the store with which `for_loop` binds the loop's
+/// variable to its counter.
+pub fn is_generated_store(variable: &str, value: &Expr) -> bool {
+    match *value {
+        _ if variable.starts_with('#') => true,
+        Expr::Identifier(ref name) => name.starts_with("#for"),
+        _ => false,
+    }
+}
+
+/// Rename the variable `from` to `to` throughout `expr`. Where a `let` in it
+/// shadows `from`, its variable already has a name of its own.
+fn rename(expr: &mut Expr, from: &str, to: &str) {
+    let rename = |expr: &mut Expr| rename(expr, from, to);
+    match *expr {
+        Expr::Literal(..) | Expr::Bool(_) | Expr::Float(_) | Expr::Str(_) | Expr::Break => {}
+        Expr::Continue | Expr::Label(_) | Expr::Goto(_) => {}
+        Expr::Identifier(ref mut name) | Expr::GlobalDataAddr(ref mut name) => {
+            if name == from {
+                *name = to.to_string();
+            }
+        }
+        Expr::Assign(ref mut name, ref mut value) => {
+            if name == from {
+                *name = to.to_string();
+            }
+            rename(value);
+        }
+        Expr::Eq(ref mut lhs, ref mut rhs)
+        | Expr::Ne(ref mut lhs, ref mut rhs)
+        | Expr::Lt(ref mut lhs, ref mut rhs)
+        | Expr::Le(ref mut lhs, ref mut rhs)
+        | Expr::Gt(ref mut lhs, ref mut rhs)
+        | Expr::Ge(ref mut lhs, ref mut rhs)
+        | Expr::Add(ref mut lhs, ref mut rhs)
+        | Expr::Sub(ref mut lhs, ref mut rhs)
+        | Expr::Mul(ref mut lhs, ref mut rhs)
+        | Expr::Div(ref mut lhs, ref mut rhs)
+        | Expr::Mod(ref mut lhs, ref mut rhs)
+        | Expr::BitAnd(ref mut lhs, ref mut rhs)
+        | Expr::BitOr(ref mut lhs, ref mut rhs)
+        | Expr::BitXor(ref mut lhs, ref mut rhs)
+        | Expr::Shl(ref mut lhs, ref mut rhs)
+        | Expr::Shr(ref mut lhs, ref mut rhs)
+        | Expr::UShr(ref mut lhs, ref mut rhs)
+        | Expr::Index(ref mut lhs, ref mut rhs)
+        | Expr::DerefAssign(ref mut lhs, ref mut rhs)
+        | Expr::And(ref mut lhs, ref mut rhs)
+        | Expr::Or(ref mut lhs, ref mut rhs) => {
+            rename(lhs);
+            rename(rhs);
+        }
+        Expr::IndexAssign(ref mut array, ref mut index, ref mut value) => {
+            rename(array);
+            rename(index);
+            rename(value);
+        }
+        Expr::IfElse(ref mut condition, ref mut then_body, ref mut else_body)
+        | Expr::WhileLoop(ref mut condition, ref mut then_body, ref mut else_body) => {
+            rename(condition);
+            for stmt in then_body.iter_mut().chain(else_body) {
+                rename(stmt);
+            }
+        }
+        Expr::DoWhile(ref mut loop_body, ref mut condition) => {
+            for stmt in loop_body {
+                rename(stmt);
+            }
+            rename(condition);
+        }
+        Expr::Repeat(ref mut count, ref mut loop_body) => {
+            rename(count);
+            for stmt in loop_body {
+                rename(stmt);
+            }
+        }
+        Expr::Call(_, ref mut args) => {
+            for arg in args {
+                rename(arg);
+            }
+        }
+        Expr::Neg(ref mut expr)
+        | Expr::Not(ref mut expr)
+        | Expr::Deref(ref mut expr)
+        | Expr::Cast(ref mut expr, _)
+        | Expr::TypeOf(ref mut expr) => rename(expr),
+    }
+}
+
+/// Whether `name` is that of a user-defined operator's function.
+pub fn is_operator_function(name: &str) -> bool {
+    name.starts_with("operator ")
+}
+
+/// Find the 1-based line and column of the byte offset `pos` in `input`.
+pub fn line_column(input: &str, pos: usize) -> (usize, usize) {
+    let before = &input[..pos];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line, column)
+}
+
+/// Expressions are written out with every operation in parentheses, to show
+/// how precedence and associativity grouped them. Blocks are written on one
+/// line, with their statements separated by semicolons. Sugar such as `cond`
+/// and `|>` is written out as what it stands for.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Literal(ref digits, radix) => {
+                let prefix = match radix {
+                    16 => "0x",
+                    8 => "0o",
+                    2 => "0b",
+                    _ => "",
+                };
+                match digits.chars().next() {
+                    Some('-') => write!(f, "-{}{}", prefix, &digits[1..]),
+                    _ => write!(f, "{}{}", prefix, digits),
+                }
+            }
+            Expr::Bool(value) => write!(f, "{}", value),
+            Expr::Float(ref digits) => write!(f, "{}", digits),
+            Expr::Identifier(ref name) => write!(f, "{}", name),
+            Expr::Assign(ref name, ref expr) => write!(f, "({} = {})", name, expr),
+            Expr::Eq(ref lhs, ref rhs) => write!(f, "({} == {})", lhs, rhs),
+            Expr::Ne(ref lhs, ref rhs) => write!(f, "({} != {})", lhs, rhs),
+            Expr::Lt(ref lhs, ref rhs) => write!(f, "({} < {})", lhs, rhs),
+            Expr::Le(ref lhs, ref rhs) => write!(f, "({} <= {})", lhs, rhs),
+            Expr::Gt(ref lhs, ref rhs) => write!(f, "({} > {})", lhs, rhs),
+            Expr::Ge(ref lhs, ref rhs) => write!(f, "({} >= {})", lhs, rhs),
+            Expr::Add(ref lhs, ref rhs) => write!(f, "({} + {})", lhs, rhs),
+            Expr::Sub(ref lhs, ref rhs) => write!(f, "({} - {})", lhs, rhs),
+            Expr::Mul(ref lhs, ref rhs) => write!(f, "({} * {})", lhs, rhs),
+            Expr::Div(ref lhs, ref rhs) => write!(f, "({} / {})", lhs, rhs),
+            Expr::Mod(ref lhs, ref rhs) => write!(f, "({} % {})", lhs, rhs),
+            Expr::BitAnd(ref lhs, ref rhs) => write!(f, "({} & {})", lhs, rhs),
+            Expr::BitOr(ref lhs, ref rhs) => write!(f, "({} | {})", lhs, rhs),
+            Expr::BitXor(ref lhs, ref rhs) => write!(f, "({} ^ {})", lhs, rhs),
+            Expr::Shl(ref lhs, ref rhs) => write!(f, "({} << {})", lhs, rhs),
+            Expr::Shr(ref lhs, ref rhs) => write!(f, "({} >> {})", lhs, rhs),
+            Expr::UShr(ref lhs, ref rhs) => write!(f, "({} >>> {})", lhs, rhs),
+            Expr::Neg(ref expr) => write!(f, "(-{})", expr),
+            Expr::Not(ref expr) => write!(f, "(!{})", expr),
+            Expr::Cast(ref expr, ty) => write!(f, "({} as {})", expr, ty),
+            Expr::Deref(ref pointer) => write!(f, "(*{})", pointer),
+            Expr::DerefAssign(ref pointer, ref value) => write!(f, "(*{} = {})", pointer, value),
+            Expr::Index(ref array, ref index) => write!(f, "{}[{}]", array, index),
+            Expr::IndexAssign(ref array, ref index, ref value) => {
+                write!(f, "({}[{}] = {})", array, index, value)
+            }
+            Expr::And(ref lhs, ref rhs) => write!(f, "({} && {})", lhs, rhs),
+            Expr::Or(ref lhs, ref rhs) => write!(f, "({} || {})", lhs, rhs),
+            Expr::IfElse(ref condition, ref then_body, ref else_body) => write!(
+                f,
+                "(if {} {} else {})",
+                condition,
+                Block(then_body),
+                Block(else_body)
+            ),
+            Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+                write!(f, "(while {} {}", condition, Block(loop_body))?;
+                if !else_body.is_empty() {
+                    write!(f, " else {}", Block(else_body))?;
+                }
+                write!(f, ")")
+            }
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                write!(f, "(do {} while {})", Block(loop_body), condition)
+            }
+            Expr::Repeat(ref count, ref loop_body) => {
+                write!(f, "(repeat {} {})", count, Block(loop_body))
+            }
+            Expr::Break => write!(f, "break"),
+            Expr::Continue => write!(f, "continue"),
+            Expr::Label(ref name) => write!(f, "{}:", name),
+            Expr::Goto(ref name) => write!(f, "goto {}", name),
+            Expr::Call(ref name, ref args) if is_operator_function(name) && args.len() == 2 => {
+                let op = &name["operator ".len()..];
+                write!(f, "({} {} {})", args[0], op, args[1])
+            }
+            Expr::Call(ref name, ref args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::GlobalDataAddr(ref name) => write!(f, "&{}", name),
+            Expr::Str(ref contents) => write!(f, "{:?}", contents),
+            Expr::TypeOf(ref expr) => write!(f, "typeof({})", expr),
+        }
+    }
+}
+
+/// A block of statements, for displaying as part of an expression.
+struct Block<'a>(&'a [Expr]);
+
+impl<'a> fmt::Display for Block<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "{{ }}");
+        }
+        write!(f, "{{ ")?;
+        for (i, stmt) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", stmt)?;
+        }
+        write!(f, " }}")
+    }
 }
 
 /// Include the parser code, generated from grammar.rustpeg.
+///
+/// The generated code is not ours to tidy, so lints are silenced here.
+#[allow(clippy::all, ellipsis_inclusive_range_patterns)]
 pub mod parser {
     include!(concat!(env!("OUT_DIR"), "/grammar.rs"));
 }
@@ -0,0 +1,432 @@
+//! A tree-walking interpreter for toy-language AST nodes.
+//!
+//! This evaluates the AST directly, without generating any code, so that
+//! programs can be run without Cranelift and compared against the JIT'd
+//! versions. It mirrors the semantics of the translation in `jit.rs`: all
+//! values are pointer-sized integers, arithmetic wraps, division rounds
+//! towards zero, comparisons are signed, shift amounts are taken modulo the
+//! number of bits, and variables which haven't been assigned yet read as zero.
+
+use std::collections::HashMap;
+
+use analysis;
+use frontend::*;
+
+/// The state needed to interpret calls: the functions which can be called,
+/// and the host-provided constants.
+pub struct Interpreter<'a> {
+    pub functions: &'a HashMap<String, Function>,
+    pub constants: &'a HashMap<String, i64>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Call the function `name` with the given arguments.
+    pub fn call(&self, name: &str, args: &[isize]) -> Result<isize, String> {
+        let function = match self.functions.get(name) {
+            Some(function) => function,
+            None => return Err(format!("cannot interpret call to `{}`", name)),
+        };
+        let params = &function.params;
+        if params.len() != args.len() {
+            return Err(format!(
+                "`{}` takes {} but {} were given",
+                name,
+                arguments(params.len()),
+                args.len()
+            ));
+        }
+
+        // Like the JIT, declare every variable the function assigns up front.
+        let mut variables = HashMap::new();
+        for stmt in &function.stmts {
+            declare_variables(&mut variables, stmt);
+        }
+        variables.insert(function.the_return.clone(), 0);
+        for (param, arg) in params.iter().zip(args) {
+            variables.insert(param.clone(), *arg);
+        }
+
+        // Only integers of the usual kind, and bools, are represented
+        // faithfully.
+        let types = analysis::variable_types(&function.types, &function.stmts);
+        let mut typed: Vec<_> = types
+            .iter()
+            .filter(|&(_, ty)| *ty != ValueType::Int && *ty != ValueType::Bool)
+            .collect();
+        typed.sort_by_key(|&(variable, _)| variable);
+        if let Some(&(variable, ty)) = typed.first() {
+            return Err(format!(
+                "cannot interpret `{}`, whose variable `{}` is of type {}",
+                name, variable, ty
+            ));
+        }
+
+        let labels = analysis::labels(&function.stmts)?;
+        let mut frame = Frame {
+            interp: self,
+            variables,
+            types,
+        };
+
+        // A `goto` unwinds to the top level, where evaluation carries on from
+        // the label.
+        let mut next = 0;
+        while let Some(stmt) = function.stmts.get(next) {
+            next = match frame.eval(stmt) {
+                Ok(_) => next + 1,
+                Err(Unwind::Goto(label)) => labels[&label],
+                Err(Unwind::Break) => return Err("`break` outside of a loop".to_string()),
+                Err(Unwind::Continue) => return Err("`continue` outside of a loop".to_string()),
+                Err(Unwind::Error(msg)) => return Err(msg),
+            };
+        }
+        Ok(frame.variables[&function.the_return])
+    }
+}
+
+/// Why evaluation stopped short of producing a value.
+enum Unwind {
+    /// A `break`, leaving the innermost enclosing loop.
+    Break,
+    /// A `continue`, going on to the next iteration of the innermost
+    /// enclosing loop.
+    Continue,
+    /// A `goto` to the named label.
+    Goto(String),
+    Error(String),
+}
+
+impl From<String> for Unwind {
+    fn from(msg: String) -> Unwind {
+        Unwind::Error(msg)
+    }
+}
+
+/// The state of a single function activation.
+struct Frame<'a, 'b: 'a> {
+    interp: &'a Interpreter<'b>,
+    variables: HashMap<String, isize>,
+    /// The types of the variables which wouldn't hold integers, which
+    /// `typeof` needs to know.
+    types: HashMap<String, ValueType>,
+}
+
+impl<'a, 'b> Frame<'a, 'b> {
+    /// Evaluate a sequence of statements, producing the value of the last one,
+    /// or zero if there are none.
+    fn eval_stmts(&mut self, stmts: &[Expr]) -> Result<isize, Unwind> {
+        let mut value = 0;
+        for stmt in stmts {
+            value = self.eval(stmt)?;
+        }
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<isize, Unwind> {
+        Ok(match *expr {
+            Expr::Literal(ref literal, radix) => {
+                let imm = i64::from_str_radix(literal, radix).map_err(|e| format!("{}", e))?;
+                imm as isize
+            }
+            Expr::Bool(value) => value as isize,
+
+            Expr::Add(ref lhs, ref rhs) => self.eval(lhs)?.wrapping_add(self.eval(rhs)?),
+            Expr::Sub(ref lhs, ref rhs) => self.eval(lhs)?.wrapping_sub(self.eval(rhs)?),
+            Expr::Mul(ref lhs, ref rhs) => self.eval(lhs)?.wrapping_mul(self.eval(rhs)?),
+            Expr::Div(ref lhs, ref rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                if rhs == 0 {
+                    return Err("division by zero".to_string().into());
+                }
+                match lhs.checked_div(rhs) {
+                    Some(quotient) => quotient,
+                    None => return Err("division overflows".to_string().into()),
+                }
+            }
+            Expr::Mod(ref lhs, ref rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                if rhs == 0 {
+                    return Err("division by zero".to_string().into());
+                }
+                lhs.wrapping_rem(rhs)
+            }
+
+            Expr::BitAnd(ref lhs, ref rhs) => self.eval(lhs)? & self.eval(rhs)?,
+            Expr::BitOr(ref lhs, ref rhs) => self.eval(lhs)? | self.eval(rhs)?,
+            Expr::BitXor(ref lhs, ref rhs) => self.eval(lhs)? ^ self.eval(rhs)?,
+
+            // Like Cranelift's shifts, these only use the low bits of the
+            // shift amount.
+            Expr::Shl(ref lhs, ref rhs) => self.eval(lhs)?.wrapping_shl(self.eval(rhs)? as u32),
+            Expr::Shr(ref lhs, ref rhs) => self.eval(lhs)?.wrapping_shr(self.eval(rhs)? as u32),
+            Expr::UShr(ref lhs, ref rhs) => {
+                let lhs = self.eval(lhs)? as usize;
+                lhs.wrapping_shr(self.eval(rhs)? as u32) as isize
+            }
+
+            Expr::Neg(ref expr) => self.eval(expr)?.wrapping_neg(),
+            Expr::Not(ref expr) => (self.eval(expr)? == 0) as isize,
+
+            Expr::Eq(ref lhs, ref rhs) => (self.eval(lhs)? == self.eval(rhs)?) as isize,
+            Expr::Ne(ref lhs, ref rhs) => (self.eval(lhs)? != self.eval(rhs)?) as isize,
+            Expr::Lt(ref lhs, ref rhs) => (self.eval(lhs)? < self.eval(rhs)?) as isize,
+            Expr::Le(ref lhs, ref rhs) => (self.eval(lhs)? <= self.eval(rhs)?) as isize,
+            Expr::Gt(ref lhs, ref rhs) => (self.eval(lhs)? > self.eval(rhs)?) as isize,
+            Expr::Ge(ref lhs, ref rhs) => (self.eval(lhs)? >= self.eval(rhs)?) as isize,
+
+            Expr::And(ref lhs, ref rhs) => (self.eval(lhs)? != 0 && self.eval(rhs)? != 0) as isize,
+            Expr::Or(ref lhs, ref rhs) => (self.eval(lhs)? != 0 || self.eval(rhs)? != 0) as isize,
+
+            // The argument of `len` is a string, whose length is known
+            // without evaluating it.
+            Expr::Call(ref name, ref args) if Builtin::lookup(name) == Some(Builtin::Len) => {
+                string_len(args)? as isize
+            }
+
+            Expr::Call(ref name, ref args) => {
+                let args = match self.interp.functions.get(name) {
+                    Some(callee) => callee.bind_arguments(args.clone())?,
+                    None => args.clone(),
+                };
+
+                // Arguments are evaluated left to right, as in the JIT.
+                let mut arg_values = Vec::new();
+                for arg in &args {
+                    arg_values.push(self.eval(arg)?);
+                }
+                match Builtin::lookup(name) {
+                    Some(Builtin::AssertEq) => return Ok(assert_eq(&arg_values)?),
+                    Some(Builtin::Isqrt) => return Ok(isqrt(&arg_values)?),
+                    Some(Builtin::Len) => unreachable!(),
+                    Some(Builtin::Array) | Some(Builtin::Load) | Some(Builtin::Store)
+                    | Some(Builtin::Alloc) | Some(Builtin::Free) => {
+                        return Err(
+                            format!("cannot interpret `{}`, which uses memory", name).into()
+                        );
+                    }
+                    Some(Builtin::F32) | Some(Builtin::F64) | Some(Builtin::I32)
+                    | Some(Builtin::I64) => {
+                        return Err(format!("cannot interpret the conversion `{}`", name).into());
+                    }
+                    None => {}
+                }
+                self.interp.call(name, &arg_values)?
+            }
+
+            Expr::GlobalDataAddr(ref name) => {
+                return Err(format!("cannot interpret the address of `{}`", name).into());
+            }
+
+            Expr::Index(..) | Expr::IndexAssign(..) | Expr::Deref(_) | Expr::DerefAssign(..) => {
+                return Err(format!("cannot interpret `{}`, which uses memory", expr).into());
+            }
+
+            Expr::Cast(..) => {
+                return Err(format!("cannot interpret the cast `{}`", expr).into());
+            }
+
+            Expr::Float(ref digits) => {
+                return Err(format!("cannot interpret the floating-point value {}", digits).into());
+            }
+
+            Expr::Str(_) => {
+                return Err("cannot interpret the address of a string"
+                    .to_string()
+                    .into())
+            }
+
+            Expr::TypeOf(ref expr) => analysis::type_of(expr, &self.types).tag() as isize,
+
+            Expr::Identifier(ref name) => match self.variables.get(name) {
+                Some(value) => *value,
+                None => match self.interp.constants.get(name) {
+                    Some(value) => *value as isize,
+                    None => return Err(format!("variable `{}` is not defined", name).into()),
+                },
+            },
+
+            Expr::Assign(ref name, ref expr) => {
+                let new_value = self.eval(expr)?;
+                self.variables.insert(name.clone(), new_value);
+                new_value
+            }
+
+            Expr::IfElse(ref condition, ref then_body, ref else_body) => {
+                if self.eval(condition)? != 0 {
+                    self.eval_stmts(then_body)?
+                } else {
+                    self.eval_stmts(else_body)?
+                }
+            }
+
+            Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+                loop {
+                    if self.eval(condition)? == 0 {
+                        self.eval_stmts(else_body)?;
+                        break;
+                    }
+                    match self.eval_stmts(loop_body) {
+                        Err(Unwind::Break) => break,
+                        Err(Unwind::Continue) => continue,
+                        result => result?,
+                    };
+                }
+                0
+            }
+
+            Expr::DoWhile(ref loop_body, ref condition) => {
+                loop {
+                    match self.eval_stmts(loop_body) {
+                        Err(Unwind::Break) => break,
+                        Err(Unwind::Continue) => 0,
+                        result => result?,
+                    };
+                    if self.eval(condition)? == 0 {
+                        break;
+                    }
+                }
+                0
+            }
+
+            Expr::Repeat(ref count, ref loop_body) => {
+                let count = self.eval(count)?;
+                for _ in 0..count {
+                    match self.eval_stmts(loop_body) {
+                        Err(Unwind::Break) => break,
+                        Err(Unwind::Continue) => continue,
+                        result => result?,
+                    };
+                }
+                0
+            }
+
+            Expr::Break => return Err(Unwind::Break),
+            Expr::Continue => return Err(Unwind::Continue),
+            Expr::Goto(ref label) => return Err(Unwind::Goto(label.clone())),
+            Expr::Label(_) => 0,
+        })
+    }
+}
+
+/// The `assert_eq` builtin, which the JIT lowers to a trap.
+fn assert_eq(args: &[isize]) -> Result<isize, String> {
+    match *args {
+        [lhs, rhs] if lhs == rhs => Ok(0),
+        [lhs, rhs] => Err(format!("assertion failed: {} != {}", lhs, rhs)),
+        _ => Err(format!(
+            "`assert_eq` takes 2 arguments but {} were given",
+            args.len()
+        )),
+    }
+}
+
+/// The `isqrt` builtin, using the same method as the JIT.
+fn isqrt(args: &[isize]) -> Result<isize, String> {
+    let x = match *args {
+        [x] if x < 0 => return Err(format!("`isqrt` of negative number {}", x)),
+        [x] => x as usize,
+        _ => {
+            return Err(format!(
+                "`isqrt` takes 1 argument but {} were given",
+                args.len()
+            ))
+        }
+    };
+    if x < 2 {
+        return Ok(x as isize);
+    }
+    let mut guess = x;
+    loop {
+        let next = (guess + x / guess) >> 1;
+        if next >= guess {
+            return Ok(guess as isize);
+        }
+        guess = next;
+    }
+}
+
+/// Recursively descend through the AST, declaring every assigned variable
+/// with an initial value of zero.
+fn declare_variables(variables: &mut HashMap<String, isize>, expr: &Expr) {
+    match *expr {
+        Expr::Assign(ref name, ref expr) => {
+            variables.insert(name.clone(), 0);
+            declare_variables(variables, expr);
+        }
+        Expr::IfElse(ref condition, ref then_body, ref else_body) => {
+            declare_variables(variables, condition);
+            for stmt in then_body.iter().chain(else_body) {
+                declare_variables(variables, stmt);
+            }
+        }
+        Expr::WhileLoop(ref condition, ref loop_body, ref else_body) => {
+            declare_variables(variables, condition);
+            for stmt in loop_body.iter().chain(else_body) {
+                declare_variables(variables, stmt);
+            }
+        }
+        Expr::DoWhile(ref loop_body, ref condition) => {
+            for stmt in loop_body {
+                declare_variables(variables, stmt);
+            }
+            declare_variables(variables, condition);
+        }
+        Expr::Repeat(ref count, ref loop_body) => {
+            declare_variables(variables, count);
+            for stmt in loop_body {
+                declare_variables(variables, stmt);
+            }
+        }
+        Expr::Eq(ref lhs, ref rhs)
+        | Expr::Ne(ref lhs, ref rhs)
+        | Expr::Lt(ref lhs, ref rhs)
+        | Expr::Le(ref lhs, ref rhs)
+        | Expr::Gt(ref lhs, ref rhs)
+        | Expr::Ge(ref lhs, ref rhs)
+        | Expr::Add(ref lhs, ref rhs)
+        | Expr::Sub(ref lhs, ref rhs)
+        | Expr::Mul(ref lhs, ref rhs)
+        | Expr::Div(ref lhs, ref rhs)
+        | Expr::Mod(ref lhs, ref rhs)
+        | Expr::BitAnd(ref lhs, ref rhs)
+        | Expr::BitOr(ref lhs, ref rhs)
+        | Expr::BitXor(ref lhs, ref rhs)
+        | Expr::Shl(ref lhs, ref rhs)
+        | Expr::Shr(ref lhs, ref rhs)
+        | Expr::UShr(ref lhs, ref rhs)
+        | Expr::Index(ref lhs, ref rhs)
+        | Expr::DerefAssign(ref lhs, ref rhs)
+        | Expr::And(ref lhs, ref rhs)
+        | Expr::Or(ref lhs, ref rhs) => {
+            declare_variables(variables, lhs);
+            declare_variables(variables, rhs);
+        }
+        Expr::IndexAssign(ref array, ref index, ref value) => {
+            declare_variables(variables, array);
+            declare_variables(variables, index);
+            declare_variables(variables, value);
+        }
+        Expr::Call(_, ref args) => {
+            for arg in args {
+                declare_variables(variables, arg);
+            }
+        }
+        Expr::Neg(ref expr)
+        | Expr::Not(ref expr)
+        | Expr::Deref(ref expr)
+        | Expr::Cast(ref expr, _)
+        | Expr::TypeOf(ref expr) => declare_variables(variables, expr),
+        Expr::Literal(..)
+        | Expr::Bool(_)
+        | Expr::Float(_)
+        | Expr::Identifier(_)
+        | Expr::GlobalDataAddr(_)
+        | Expr::Str(_)
+        | Expr::Break
+        | Expr::Continue
+        | Expr::Label(_)
+        | Expr::Goto(_) => {}
+    }
+}
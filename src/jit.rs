@@ -1,12 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
+use analysis;
+use cranelift::codegen::flowgraph::ControlFlowGraph;
+use cranelift::codegen::ir::{ArgumentLoc, ArgumentPurpose, StackSlot};
+use cranelift::codegen::CodegenError;
 use cranelift::prelude::*;
-use cranelift_module::{DataContext, Linkage, Module};
+use cranelift_module::{DataContext, DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError};
+use cranelift_native;
 use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
 use frontend::*;
+use interp::Interpreter;
 use std::slice;
 
+/// The trap code used when an `assert_eq` fails.
+pub const ASSERT_EQ_TRAP: u16 = 1;
+
+/// The trap code used when `isqrt` is given a negative number.
+pub const ISQRT_TRAP: u16 = 2;
+
+/// The operations an overflow handler set with `set_overflow_handler` may be
+/// called for.
+pub const OVERFLOW_ADD: isize = 1;
+pub const OVERFLOW_SUB: isize = 2;
+pub const OVERFLOW_MUL: isize = 3;
+
 /// The basic JIT class.
+#[allow(clippy::upper_case_acronyms)]
 pub struct JIT {
     /// The function builder context, which is reused across multiple
     /// FunctionBuilder instances.
@@ -23,6 +43,138 @@ pub struct JIT {
     /// The module, with the simplejit backend, which manages the JIT'd
     /// functions.
     module: Module<SimpleJITBackend>,
+
+    /// A description of the host, like the one the module compiles for, for
+    /// answering questions about its ABI.
+    isa: Box<dyn isa::TargetIsa>,
+
+    /// Named constants provided by the host, which toy programs can refer to
+    /// like variables.
+    constants: HashMap<String, i64>,
+
+    /// The AST of each function compiled so far, so that the interpreter can
+    /// call them too, and so that calls to them can be inlined.
+    functions: HashMap<String, Function>,
+
+    /// Functions provided by the host, with their signatures.
+    host_functions: HashMap<String, HostFunction>,
+
+    /// The function each alias defined with `alias` refers to.
+    aliases: HashMap<String, String>,
+
+    /// The structs, enums and globals defined with `struct`, `enum` and
+    /// `global`.
+    definitions: Definitions,
+
+    /// The prologue and epilogue hook, if one has been set.
+    hook: Option<Box<Hook>>,
+
+    /// The function to call whenever the right operand of `&&` or `||` is
+    /// evaluated, if one has been set.
+    rhs_callback: Option<*const u8>,
+
+    /// The function to call when addition, subtraction, or multiplication
+    /// overflows, if one has been set.
+    overflow_handler: Option<*const u8>,
+
+    /// The number of expressions compiled by `eval_expr`, used to give each
+    /// of their functions a unique name.
+    evals: usize,
+
+    /// Warnings about the functions compiled so far.
+    warnings: Vec<String>,
+
+    /// Whether negating the most negative integer traps, rather than
+    /// wrapping around to itself.
+    checked_negation: bool,
+
+    /// The most EBBs a function may be translated into, if there's a limit.
+    max_ebbs: Option<usize>,
+
+    /// Whether to check that every EBB is sealed exactly once.
+    check_sealing: bool,
+
+    /// The longest source, in bytes, which will be parsed, if there's a
+    /// limit.
+    max_source_len: Option<usize>,
+
+    /// The flags which satisfy `#[cfg(flag)]` attributes.
+    cfg_flags: HashSet<String>,
+
+    /// The size of each function's machine code, once it's defined.
+    code_sizes: HashMap<String, usize>,
+
+    /// Measurements of each function's translation, once it's defined.
+    stats: HashMap<String, CompileStats>,
+
+    /// The address and size of each function's machine code, once it's
+    /// finalized.
+    code: HashMap<String, (*const u8, usize)>,
+
+    /// The functions each function calls, other than builtins, host
+    /// functions, and functions which were inlined, once it's defined.
+    callees: HashMap<String, Vec<String>>,
+
+    /// Functions which have been defined, but not finalized yet.
+    unfinalized: Vec<(String, FuncId)>,
+
+    /// The data objects holding string literals which have been defined.
+    strings: HashSet<DataId>,
+}
+
+/// The largest constant count for which a `repeat` loop is unrolled.
+const REPEAT_UNROLL_LIMIT: i64 = 4;
+
+/// The most integers an `array` may hold, so that a few of them can't
+/// overflow the stack.
+const MAX_ARRAY_LEN: i64 = 4096;
+
+/// The longest `array` which is zeroed by a store for each element, rather
+/// than by a loop.
+const ARRAY_UNROLL_LIMIT: i64 = 4;
+
+/// Where in a function a hook set with `set_hook` is being called.
+#[derive(Clone, Copy, PartialEq)]
+pub enum HookPoint {
+    /// At the start of the entry block, before the function body.
+    Entry,
+    /// Just before the function returns.
+    Exit,
+}
+
+/// A callback which can insert instructions into every function compiled.
+type Hook = dyn Fn(HookPoint, &mut FunctionBuilder);
+
+/// Measurements of how much a function's translation held in memory, for
+/// diagnosing functions which are expensive to compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompileStats {
+    /// The most variables in scope at once: those of the function itself, or
+    /// of one of the functions inlined into it, whichever had the most.
+    pub peak_variables: usize,
+    /// The number of IR instructions the function was translated into.
+    pub instructions: usize,
+    /// The number of EBBs the function was translated into.
+    pub ebbs: usize,
+}
+
+/// The result of translating a function into the context's IR.
+struct Translation {
+    /// The `Variable` each of the function's own variables was declared as.
+    variables: HashMap<String, Variable>,
+    stats: CompileStats,
+    /// The functions called through a relocation, in the order they're first
+    /// called.
+    callees: Vec<String>,
+    /// The data object declared for each string literal, and its contents.
+    strings: Vec<(DataId, String)>,
+}
+
+/// A function provided by the host, registered with `register_fn`.
+struct HostFunction {
+    param_types: Vec<Type>,
+    ret_type: Type,
+    addr: *const u8,
 }
 
 impl JIT {
@@ -33,25 +185,504 @@ impl JIT {
             unimplemented!();
         }
 
-        let builder = SimpleJITBuilder::new();
+        let mut builder = SimpleJITBuilder::with_isa(native_isa());
+        builder.symbol(FLOAT_REM, float_rem as *const u8);
         let module = Module::new(builder);
+
+        // The bounds of the integer type, which is pointer-sized, are always
+        // available as constants.
+        let shift = 64 - u32::from(module.target_config().pointer_type().bits());
+        let mut constants = HashMap::new();
+        constants.insert("INT_MAX".to_string(), i64::MAX >> shift);
+        constants.insert("INT_MIN".to_string(), i64::MIN >> shift);
+
         Self {
             builder_context: FunctionBuilderContext::new(),
             ctx: module.make_context(),
             data_ctx: DataContext::new(),
             module,
+            isa: native_isa(),
+            constants,
+            functions: HashMap::new(),
+            host_functions: HashMap::new(),
+            aliases: HashMap::new(),
+            definitions: Definitions::default(),
+            hook: None,
+            rhs_callback: None,
+            overflow_handler: None,
+            evals: 0,
+            warnings: Vec::new(),
+            checked_negation: false,
+            max_ebbs: None,
+            check_sealing: false,
+            max_source_len: None,
+            cfg_flags: HashSet::new(),
+            code_sizes: HashMap::new(),
+            stats: HashMap::new(),
+            code: HashMap::new(),
+            callees: HashMap::new(),
+            unfinalized: Vec::new(),
+            strings: HashSet::new(),
+        }
+    }
+
+    /// Choose whether, in functions compiled afterwards, negating `INT_MIN`
+    /// traps with an integer overflow, or wraps around to `INT_MIN`, which is
+    /// the default.
+    pub fn set_checked_negation(&mut self, checked: bool) {
+        self.checked_negation = checked;
+    }
+
+    /// Limit the number of EBBs each function compiled afterwards may be
+    /// translated into, or `None` to remove the limit. A function over the
+    /// limit fails to compile, before any code is generated for it, which
+    /// bounds the time spent compiling deeply nested control flow.
+    pub fn set_max_ebbs(&mut self, max_ebbs: Option<usize>) {
+        self.max_ebbs = max_ebbs;
+    }
+
+    /// Check that the translator seals each EBB of the functions translated
+    /// afterwards exactly once, reporting any it doesn't as an error rather
+    /// than leaving Cranelift to build the wrong SSA values for them. Only
+    /// the translator's own EBBs are checked, so any EBB a hook adds is left
+    /// to the hook to seal.
+    pub fn set_check_sealing(&mut self, check: bool) {
+        self.check_sealing = check;
+    }
+
+    /// Limit the length, in bytes, of the source which may be compiled
+    /// afterwards, or `None` to remove the limit. Longer source is rejected
+    /// before it's parsed, so that embedders taking programs from elsewhere
+    /// can bound the work an enormous one would make.
+    pub fn set_max_source_len(&mut self, max_source_len: Option<usize>) {
+        self.max_source_len = max_source_len;
+    }
+
+    /// Enable exactly the given flags for `#[cfg(flag)]` attributes in the
+    /// functions parsed afterwards. Statements and functions whose attribute
+    /// names a flag which isn't enabled are left out, as though they weren't
+    /// written at all.
+    pub fn set_cfg_flags(&mut self, flags: &[&str]) {
+        self.cfg_flags = flags.iter().map(|flag| flag.to_string()).collect();
+    }
+
+    /// The names of the functions built into the language. Calls to any other
+    /// name are calls to a toy, host, or `dlsym`'d function.
+    pub fn builtins() -> Vec<String> {
+        BUILTINS
+            .iter()
+            .map(|builtin| builtin.0.to_string())
+            .collect()
+    }
+
+    /// Take the warnings about the functions compiled since the last call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// Set a hook which is called at the entry and exit of every function
+    /// compiled afterwards, with the builder positioned where it can insert
+    /// instructions, for example to instrument the function.
+    pub fn set_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(HookPoint, &mut FunctionBuilder) + 'static,
+    {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook set with `set_hook`, if any.
+    pub fn clear_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// Set a function to be called each time the right operand of a `&&` or
+    /// `||` is evaluated, in functions compiled afterwards, or `None` to stop
+    /// calling one. This makes it possible to check which operands were
+    /// short-circuited.
+    pub fn set_rhs_callback(&mut self, callback: Option<extern "C" fn()>) {
+        self.rhs_callback = callback.map(|f| f as *const u8);
+    }
+
+    /// Set a function to be called, in functions compiled afterwards, when
+    /// `+`, `-`, or `*` overflows, or `None` to let them wrap around. The
+    /// handler is passed one of `OVERFLOW_ADD`, `OVERFLOW_SUB`, or
+    /// `OVERFLOW_MUL`, and the operands, and what it returns is used as the
+    /// result of the operation.
+    pub fn set_overflow_handler(
+        &mut self,
+        handler: Option<extern "C" fn(isize, isize, isize) -> isize>,
+    ) {
+        self.overflow_handler = handler.map(|f| f as *const u8);
+    }
+
+    /// Register a host function which toy programs can call by name.
+    ///
+    /// Functions which are only found with `dlsym`, like `puts`, are assumed
+    /// to take and return pointer-sized integers. Registered functions are
+    /// called with the signature given here instead, and the toy language's
+    /// integer values are converted to and from the parameter and return
+    /// types around the call. Integer and floating-point types are supported.
+    /// Arguments which are already floats are only converted if the parameter
+    /// is of another type.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        param_types: &[Type],
+        ret_type: Type,
+        addr: *const u8,
+    ) -> Result<(), String> {
+        for ty in param_types.iter().chain(Some(&ret_type)) {
+            if !ty.is_int() && !ty.is_float() {
+                return Err(format!("`{}` uses unsupported type {}", name, ty));
+            }
         }
+        self.host_functions.insert(
+            name.to_string(),
+            HostFunction {
+                param_types: param_types.to_vec(),
+                ret_type,
+                addr,
+            },
+        );
+        Ok(())
+    }
+
+    /// Define a named constant. Functions compiled afterwards can use the name
+    /// in expressions, and its value is substituted at compile time.
+    pub fn define_constant(&mut self, name: &str, value: i64) {
+        self.constants.insert(name.to_string(), value);
     }
 
     /// Compile a string in the toy language into machine code.
     pub fn compile(&mut self, input: &str) -> Result<*const u8, String> {
         // First, parse the string, producing AST nodes.
-        let (name, params, the_return, stmts) =
-            parser::function(&input).map_err(|e| e.to_string())?;
+        let function = self.parse_function(input)?;
 
-        // Then, translate the AST nodes into Cranelift IR.
-        self.translate(params, the_return, stmts)
+        self.compile_function(function)
+    }
+
+    /// Compile a string in the toy language containing any number of
+    /// functions into machine code, returning a pointer to each by name.
+    ///
+    /// A program may also define aliases, with `alias bar = foo`. An alias is
+    /// another name for the same code, so it doesn't generate any more. Its
+    /// constants, defined with `const WIDTH = 640`, are like those from
+    /// `define_constant`, and each may use those defined before it. The
+    /// structs and enums it defines, with `struct Point { x, y }` and `enum
+    /// Color { Red, Green, Blue }`, and the globals it declares, with `global
+    /// counter = 0`, can be used by its functions, and by functions compiled
+    /// afterwards.
+    pub fn compile_program(&mut self, input: &str) -> Result<HashMap<String, *const u8>, String> {
+        self.check_source_len(input)?;
+        let (definitions, globals) =
+            program_definitions(input, &self.cfg_flags, &self.definitions)?;
+        let items =
+            parser::program(input, &self.cfg_flags, &definitions).map_err(|e| e.to_string())?;
+        let mut functions = Vec::new();
+        let mut aliases = Vec::new();
+        let mut constants = self.constants.clone();
+        for item in items {
+            match item {
+                Item::Function(function) => functions.push(function),
+                Item::Alias(name, target) => aliases.push((name, target)),
+                Item::Const(name, value) => {
+                    if constants.contains_key(&name) {
+                        return Err(format!("constant `{}` is defined more than once", name));
+                    }
+                    let value = analysis::constant_value(&value, &constants)
+                        .ok_or_else(|| format!("the value of `{}` isn't a constant", name))?;
+                    constants.insert(name, value);
+                }
+            }
+        }
+
+        // Check all the definitions before compiling any of them, so that a
+        // mistake doesn't leave the program partially compiled.
+        let mut names = HashSet::new();
+        for name in functions
+            .iter()
+            .map(|f| &f.name)
+            .chain(aliases.iter().map(|a| &a.0))
+        {
+            if !names.insert(name) {
+                return Err(format!("function `{}` is defined more than once", name));
+            }
+        }
+        for (name, target) in &aliases {
+            if !names.contains(target) && !self.functions.contains_key(target) {
+                return Err(format!(
+                    "alias `{}` refers to undefined function `{}`",
+                    name, target
+                ));
+            }
+            if self.aliases.contains_key(target) || aliases.iter().any(|a| a.0 == *target) {
+                return Err(format!("alias `{}` refers to another alias", name));
+            }
+        }
+        for function in &functions {
+            analysis::check_defaults(function, &constants)?;
+        }
+
+        // Calls to an alias are calls to its target, so the aliases need to
+        // be known before any of the functions are translated. If one of the
+        // functions turns out not to translate, they're all put back as they
+        // were.
+        let saved = (
+            self.aliases.clone(),
+            self.functions.clone(),
+            self.definitions.clone(),
+            self.constants.clone(),
+        );
+        for (name, target) in &aliases {
+            self.aliases.insert(name.clone(), target.clone());
+        }
+        self.definitions = definitions;
+        self.constants = constants;
+
+        if let Err(err) = self.declare_program(&functions) {
+            let (aliases, functions, definitions, constants) = saved;
+            self.aliases = aliases;
+            self.functions = functions;
+            self.definitions = definitions;
+            self.constants = constants;
+            return Err(err);
+        }
+
+        // Each global is a data object holding an integer, which starts out
+        // as its initial value.
+        for (name, value) in globals {
+            self.data_ctx
+                .define((value as isize).to_ne_bytes().to_vec().into_boxed_slice());
+            let defined = match self.module.declare_data(&name, Linkage::Export, true) {
+                Ok(id) => self.module.define_data(id, &self.data_ctx),
+                Err(err) => Err(err),
+            };
+            self.data_ctx.clear();
+            defined.map_err(|e| e.to_string())?;
+        }
+
+        // Only functions which were defined can be finalized.
+        let defined: Vec<String> = functions.iter().map(|f| f.name.clone()).collect();
+        for function in functions {
+            let name = function.name.clone();
+            let id = self.define_function(function)?;
+            self.unfinalized.push((name, id));
+        }
+
+        // Nothing can be finalized until everything it calls is defined, so
+        // finalize the whole program at once.
+        self.finalize();
+        let mut codes = HashMap::new();
+        for name in defined {
+            let code = self.code[&name].0;
+            codes.insert(name, code);
+        }
+
+        // Look up the code of each alias's target, which may have been
+        // compiled before this program.
+        for (name, target) in aliases {
+            let code = match self.module.get_name(&target) {
+                Some(FuncOrDataId::Func(id)) => self.module.get_finalized_function(id),
+                _ => return Err(format!("function `{}` has not been compiled", target)),
+            };
+            let function = self.functions[&target].clone();
+            self.functions.insert(name.clone(), function);
+            codes.insert(name, code);
+        }
+        Ok(codes)
+    }
+
+    /// Declare the functions of a program, and check that each of them
+    /// translates, without defining any of them.
+    fn declare_program(&mut self, functions: &[Function]) -> Result<(), String> {
+        // Declare every function before defining any of them, so that calls
+        // to functions later in the program, including mutually recursive
+        // ones, use the right signature. Their ASTs are recorded up front too,
+        // so that they can be inlined regardless of order.
+        for function in functions {
+            let types = analysis::variable_types(&function.types, &function.stmts);
+            let sig = self.signature(function, &types);
+            self.module
+                .declare_function(&function.name, Linkage::Export, &sig)
+                .map_err(|e| e.to_string())?;
+            self.functions
+                .insert(function.name.clone(), function.clone());
+        }
+
+        // What has been defined can't be taken back, and a function which
+        // calls one that failed to translate could never be finalized, so
+        // they're all translated, and their IR verified, once before any of
+        // them is defined.
+        for function in functions {
+            let translated = self.translate(function.clone()).and_then(|_| {
+                self.ctx.verify(&*self.isa).map_err(|errors| {
+                    let err = ModuleError::Compilation(CodegenError::Verifier(errors));
+                    codegen_error(&function.name, err)
+                })
+            });
+            self.module.clear_context(&mut self.ctx);
+            translated?;
+        }
+        Ok(())
+    }
+
+    /// Compile and run a single expression in the toy language, such as
+    /// `2 + 3 * 4`, without having to wrap it in a function. An integer of
+    /// another width is converted to an `isize`, but a float is an error.
+    pub fn eval_expr(&mut self, input: &str) -> Result<isize, String> {
+        self.check_source_len(input)?;
+        let expr = parser::bare_expression(input, &self.cfg_flags, &self.definitions)
+            .map_err(|e| e.to_string())?;
+
+        // The expression becomes the body of a function with no parameters.
+        // Neither of the names can be written in the toy language, so they
+        // can't clash with anything the expression refers to.
+        self.evals += 1;
+        let the_return = "#result".to_string();
+        let mut function = Function {
+            name: format!("#eval{}", self.evals),
+            params: Vec::new(),
+            the_return: the_return.clone(),
+            stmts: vec![Expr::Assign(the_return.clone(), Box::new(expr))],
+            inline: Inline::Default,
+            defaults: Vec::new(),
+            types: HashMap::new(),
+        };
+        let ty = analysis::return_type(&function);
+        if ty == ValueType::F32 || ty == ValueType::F64 {
+            return Err(format!(
+                "`{}` is of type {}, not an integer",
+                input.trim(),
+                ty
+            ));
+        }
+        function.types.insert(the_return, ValueType::Int);
+        let code = self.compile_function(function)?;
+        let code = unsafe { mem::transmute::<*const u8, fn() -> isize>(code) };
+        Ok(code())
+    }
+
+    /// Parse a single expression, as `eval_expr` would, and write it back out
+    /// with every operation in parentheses, showing how precedence and
+    /// associativity grouped it: `2 + 3 * 4` is `(2 + (3 * 4))`. Nothing is
+    /// compiled.
+    pub fn parse_expr_tree(&self, input: &str) -> Result<String, String> {
+        self.check_source_len(input)?;
+        let expr = parser::bare_expression(input, &self.cfg_flags, &self.definitions)
             .map_err(|e| e.to_string())?;
+        Ok(expr.to_string())
+    }
+
+    /// Compile a string in the toy language into a function which can be
+    /// called with any number of arguments, returning an error rather than
+    /// misbehaving if the number is wrong. Its parameters and its result must
+    /// all be pointer-sized integers.
+    pub fn compile_dynamic(&mut self, input: &str) -> Result<DynFunc, String> {
+        let function = self.parse_function(input)?;
+        let arity = function.params.len();
+        if arity > DynFunc::MAX_ARITY {
+            return Err(format!(
+                "`{}` has {} parameters, but dynamic calls support at most {}",
+                function.name,
+                arity,
+                DynFunc::MAX_ARITY
+            ));
+        }
+        let int = self.module.target_config().pointer_type();
+        let types = analysis::variable_types(&function.types, &function.stmts);
+        for name in function.params.iter().chain(Some(&function.the_return)) {
+            if ir_type(int, &types, name) != int {
+                return Err(format!(
+                    "`{}` can't be called dynamically, since `{}` is of type {}",
+                    function.name, name, types[name]
+                ));
+            }
+        }
+
+        let code = self.compile_function(function)?;
+        Ok(DynFunc { code, arity })
+    }
+
+    /// Compile a parsed function into machine code.
+    fn compile_function(&mut self, function: Function) -> Result<*const u8, String> {
+        let name = function.name.clone();
+        let id = self.define_function(function)?;
+        self.unfinalized.push((name.clone(), id));
+
+        // Finalize the functions which we just defined, which resolves any
+        // outstanding relocations (patching in addresses, now that they're
+        // available).
+        self.finalize();
+
+        // We can now retrieve a pointer to the machine code.
+        Ok(self.code[&name].0)
+    }
+
+    /// Translate a function in the toy language and define it, but don't
+    /// finalize it. Unlike with `compile`, it may call functions which
+    /// haven't been compiled yet, as long as they're defined, or provided by
+    /// the host process, by the time the next function is compiled, which
+    /// finalizes them all. Its machine code is available from `code_bytes`
+    /// from then on.
+    pub fn define(&mut self, input: &str) -> Result<(), String> {
+        let function = self.parse_function(input)?;
+        let name = function.name.clone();
+        let id = self.define_function(function)?;
+        self.unfinalized.push((name, id));
+        Ok(())
+    }
+
+    /// Finalize every function and data object defined since the last
+    /// finalization, and remember where the functions' machine code is.
+    fn finalize(&mut self) {
+        self.module.finalize_definitions();
+        for (name, id) in mem::take(&mut self.unfinalized) {
+            let code = self.module.get_finalized_function(id);
+            let size = self.code_sizes[&name];
+            self.code.insert(name, (code, size));
+        }
+    }
+
+    /// The functions which a defined but unfinalized function calls, which
+    /// haven't been defined themselves, in the order they're first called.
+    /// Finalizing the function will have to find them in the host process
+    /// instead, so a function which isn't anywhere fails to link. Once a
+    /// function is finalized, nothing is pending.
+    pub fn pending_relocations(&self, name: &str) -> Vec<String> {
+        if self.code.contains_key(name) {
+            return Vec::new();
+        }
+        match self.callees.get(name) {
+            Some(callees) => callees
+                .iter()
+                .filter(|callee| !self.code_sizes.contains_key(*callee))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Translate a parsed function and define it to the module, without
+    /// finalizing it.
+    fn define_function(&mut self, function: Function) -> Result<FuncId, String> {
+        let name = function.name.clone();
+        analysis::check_defaults(&function, &self.constants)?;
+
+        if analysis::always_recurses(&function) {
+            self.warnings.push(format!(
+                "function `{}` always calls itself with the same arguments, so it never returns",
+                name
+            ));
+        }
+
+        // Remember the AST, in case the interpreter wants to call it later,
+        // or a later function wants to inline it.
+        self.functions.insert(name.clone(), function.clone());
+
+        // Then, translate the AST nodes into Cranelift IR.
+        let translation = self.translate(function).map_err(|e| e.to_string())?;
+        self.stats.insert(name.clone(), translation.stats);
+        self.callees.insert(name.clone(), translation.callees);
 
         // Next, declare the function to simplejit. Functions must be declared
         // before they can be called, or defined.
@@ -67,24 +698,293 @@ impl JIT {
         // Define the function to simplejit. This finishes compilation, although
         // there may be outstanding relocations to perform. Currently, simplejit
         // cannot finish relocations until all functions to be called are
-        // defined. For this toy demo for now, we'll just finalize the function
-        // below.
-        self.module
-            .define_function(id, &mut self.ctx)
+        // defined, so the caller is responsible for finalizing the function.
+        if let Err(err) = self.module.define_function(id, &mut self.ctx) {
+            self.module.clear_context(&mut self.ctx);
+            return Err(codegen_error(&name, err));
+        }
+
+        // The module doesn't say how big the code is. Since the context has
+        // already been compiled, rerunning its last pass just reports the size.
+        let size = self
+            .ctx
+            .relax_branches(&*self.isa)
             .map_err(|e| e.to_string())?;
+        self.code_sizes.insert(name, size as usize);
 
         // Now that compilation is finished, we can clear out the context state.
         self.module.clear_context(&mut self.ctx);
 
-        // Finalize the functions which we just defined, which resolves any
-        // outstanding relocations (patching in addresses, now that they're
-        // available).
-        self.module.finalize_definitions();
+        // The string literals it uses are defined with it, unless an earlier
+        // function already defined them.
+        for (id, contents) in translation.strings {
+            if self.strings.insert(id) {
+                let mut bytes = contents.into_bytes();
+                bytes.push(0);
+                self.data_ctx.define(bytes.into_boxed_slice());
+                let defined = self.module.define_data(id, &self.data_ctx);
+                self.data_ctx.clear();
+                defined.map_err(|e| e.to_string())?;
+            }
+        }
 
-        // We can now retrieve a pointer to the machine code.
-        let code = self.module.get_finalized_function(id);
+        Ok(id)
+    }
+
+    /// Make the signature of a toy function, given the types of its
+    /// variables. Each parameter, and the return value, has the type of its
+    /// variable.
+    fn signature(&self, function: &Function, types: &HashMap<String, ValueType>) -> Signature {
+        let int = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        for param in &function.params {
+            sig.params.push(AbiParam::new(ir_type(int, types, param)));
+        }
+        let return_type = ir_type(int, types, &function.the_return);
+        sig.returns.push(AbiParam::new(return_type));
+        sig
+    }
+
+    /// Translate a string in the toy language into Cranelift IR, and return
+    /// the IR in its textual form. Nothing is compiled or defined.
+    pub fn ir(&mut self, input: &str) -> Result<String, String> {
+        let function = self.parse_function(input)?;
+        self.translate(function)?;
+        let ir = self.ctx.func.display(None).to_string();
+        self.module.clear_context(&mut self.ctx);
+        Ok(ir)
+    }
+
+    /// Translate a function in the toy language, and report the Cranelift
+    /// `Variable` each of its variables was declared as, with its index and
+    /// type, in the order they were declared. Parameters come first, then the
+    /// return variable, then the rest in the order they're first assigned.
+    /// Variables of inlined functions aren't included.
+    pub fn variable_map(&mut self, input: &str) -> Result<Vec<(String, usize, Type)>, String> {
+        let function = self.parse_function(input)?;
+        let int = self.module.target_config().pointer_type();
+        let types = analysis::variable_types(&function.types, &function.stmts);
+        let variables = self.translate(function)?.variables;
+        self.module.clear_context(&mut self.ctx);
+
+        let mut map: Vec<_> = variables
+            .into_iter()
+            .map(|(name, var)| {
+                let ty = ir_type(int, &types, &name);
+                (name, var.index(), ty)
+            })
+            .collect();
+        map.sort_by_key(|entry| entry.1);
+        Ok(map)
+    }
+
+    /// Translate a function in the toy language, and describe the control
+    /// flow graph of the resulting IR in Graphviz's DOT format, with a node
+    /// per EBB and an edge per branch between them.
+    pub fn cfg_dot(&mut self, input: &str) -> Result<String, String> {
+        let function = self.parse_function(input)?;
+        let name = function.name.clone();
+        self.translate(function)?;
+
+        let func = &self.ctx.func;
+        let cfg = ControlFlowGraph::with_function(func);
+        let mut dot = format!("digraph {} {{\n", name);
+        for ebb in func.layout.ebbs() {
+            dot.push_str(&format!("    {};\n", ebb));
+        }
+        for ebb in func.layout.ebbs() {
+            for succ in cfg.succ_iter(ebb) {
+                dot.push_str(&format!("    {} -> {};\n", ebb, succ));
+            }
+        }
+        dot.push_str("}\n");
+
+        self.module.clear_context(&mut self.ctx);
+        Ok(dot)
+    }
+
+    /// Describe where each parameter of a function is passed under the host's
+    /// calling convention: either the register's name, or the offset into the
+    /// stack arguments. This only parses the function; nothing is compiled.
+    pub fn param_locations(&self, input: &str) -> Result<Vec<String>, String> {
+        let function = self.parse_function(input)?;
+
+        // Build the same signature `translate` would, and let the ISA assign
+        // the parameters to their locations.
+        let types = analysis::variable_types(&function.types, &function.stmts);
+        let mut sig = self.signature(&function, &types);
+        self.isa.legalize_signature(&mut sig, true);
+
+        let reg_info = self.isa.register_info();
+        Ok(sig
+            .params
+            .iter()
+            .filter(|param| param.purpose == ArgumentPurpose::Normal)
+            .map(|param| match param.location {
+                ArgumentLoc::Stack(offset) => format!("stack+{}", offset),
+                location => location.display(&reg_info).to_string(),
+            })
+            .collect())
+    }
+
+    /// Run a function in the toy language with the given arguments, by
+    /// walking its AST rather than compiling it. It may call itself, and any
+    /// function compiled so far.
+    pub fn interpret(&self, input: &str, args: &[isize]) -> Result<isize, String> {
+        let function = self.parse_function(input)?;
+        let name = function.name.clone();
+
+        let mut functions = self.functions.clone();
+        functions.insert(name.clone(), function);
+        let interp = Interpreter {
+            functions: &functions,
+            constants: &self.constants,
+        };
+        interp.call(&name, args)
+    }
+
+    /// Report assignments in a function whose values are never read. This
+    /// only parses the function; nothing is compiled.
+    pub fn dead_stores(&self, input: &str) -> Result<Vec<String>, String> {
+        let function = self.parse_function(input)?;
+        Ok(analysis::dead_stores(&function.the_return, &function.stmts))
+    }
+
+    /// Compile a function, as `compile` does, but gather everything found
+    /// wrong with it along the way, for tools to show. As well as errors,
+    /// this includes the warnings found by `dead_stores`, and identifiers
+    /// found by `free_identifiers` are errors, found before compiling.
+    ///
+    /// The diagnostics are a JSON array of objects, each with a `severity` of
+    /// `"error"` or `"warning"`, a `kind` saying which check found it, a
+    /// `message`, and a `span` with the `line` and `column` it starts at,
+    /// which is `null` when it isn't known. Only parse errors know where they
+    /// are, since the AST doesn't record positions. There's no code if there
+    /// were any errors.
+    pub fn compile_with_diagnostics(&mut self, input: &str) -> (Option<*const u8>, String) {
+        let mut diagnostics = Vec::new();
+        let code = self.compile_diagnosed(input, &mut diagnostics);
+        let diagnostics: Vec<String> = diagnostics.iter().map(Diagnostic::to_json).collect();
+        (code, format!("[{}]", diagnostics.join(", ")))
+    }
+
+    fn compile_diagnosed(
+        &mut self,
+        input: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<*const u8> {
+        if let Err(msg) = self.check_source_len(input) {
+            diagnostics.push(Diagnostic::error("source_len", msg, None));
+            return None;
+        }
+        let function = match parser::function(input, &self.cfg_flags, &self.definitions) {
+            Ok((true, function)) => function,
+            Ok((false, function)) => {
+                diagnostics.push(Diagnostic::error("cfg", left_out(&function), None));
+                return None;
+            }
+            Err(err) => {
+                let span = Some((err.line, err.column));
+                diagnostics.push(Diagnostic::error("parse", err.to_string(), span));
+                return None;
+            }
+        };
+
+        for name in analysis::free_identifiers(&function, &self.constants) {
+            let msg = format!("`{}` is not a parameter, variable, or constant", name);
+            diagnostics.push(Diagnostic::error("undefined", msg, None));
+        }
+        for msg in analysis::dead_stores(&function.the_return, &function.stmts) {
+            diagnostics.push(Diagnostic::warning("dead_store", msg));
+        }
+        if diagnostics.iter().any(|diagnostic| diagnostic.error) {
+            return None;
+        }
+
+        let warnings = self.warnings.len();
+        let code = self.compile_function(function);
+        for msg in &self.warnings[warnings..] {
+            diagnostics.push(Diagnostic::warning("compile", msg.clone()));
+        }
+        match code {
+            Ok(code) => Some(code),
+            Err(msg) => {
+                diagnostics.push(Diagnostic::error("compile", msg, None));
+                None
+            }
+        }
+    }
+
+    /// Report identifiers in a function which aren't parameters, assigned
+    /// variables, or constants, and so would fail to compile. This only
+    /// parses the function; nothing is compiled.
+    pub fn free_identifiers(&self, input: &str) -> Result<Vec<String>, String> {
+        let function = self.parse_function(input)?;
+        Ok(analysis::free_identifiers(&function, &self.constants))
+    }
+
+    /// The name, parameters, and return variable of each function in a
+    /// program, in the order they're defined. Aliases are skipped, and so are
+    /// functions with a `#[cfg]` attribute, since no flags are enabled. This
+    /// only parses the program; nothing is compiled.
+    pub fn parse_program(input: &str) -> Result<Vec<(String, Vec<String>, String)>, String> {
+        let flags = HashSet::new();
+        let (definitions, _) = program_definitions(input, &flags, &Definitions::default())?;
+        let items = parser::program(input, &flags, &definitions).map_err(|e| e.to_string())?;
+        Ok(items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Function(function) => {
+                    Some((function.name, function.params, function.the_return))
+                }
+                Item::Alias(..) | Item::Const(..) => None,
+            })
+            .collect())
+    }
+
+    /// Check that `input` is within the limit on the length of source, if
+    /// there is one.
+    fn check_source_len(&self, input: &str) -> Result<(), String> {
+        match self.max_source_len {
+            Some(max_source_len) if input.len() > max_source_len => Err(format!(
+                "the source is {} bytes long, but at most {} are allowed",
+                input.len(),
+                max_source_len
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse a single function, which must not have been left out by its
+    /// `#[cfg]` attribute.
+    fn parse_function(&self, input: &str) -> Result<Function, String> {
+        self.check_source_len(input)?;
+        let (enabled, function) = parser::function(input, &self.cfg_flags, &self.definitions)
+            .map_err(|e| e.to_string())?;
+        if !enabled {
+            return Err(left_out(&function));
+        }
+        Ok(function)
+    }
 
-        Ok(code)
+    /// The machine code of a compiled function, or `None` if there's no
+    /// function by that name which has been finalized.
+    pub fn code_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.code
+            .get(name)
+            .map(|&(code, size)| unsafe { slice::from_raw_parts(code, size) })
+    }
+
+    /// Measurements of a compiled function's translation, or `None` if
+    /// there's no function by that name which has been defined.
+    pub fn compile_stats(&self, name: &str) -> Option<CompileStats> {
+        self.stats.get(name).cloned()
+    }
+
+    /// The size in bytes of a compiled function's machine code, or `None` if
+    /// there's no function by that name which has been defined.
+    pub fn code_size(&self, name: &str) -> Option<usize> {
+        self.code_sizes.get(name).cloned()
     }
 
     /// Create a zero-initialized data section.
@@ -101,31 +1001,48 @@ impl JIT {
             .define_data(id, &self.data_ctx)
             .map_err(|e| e.to_string())?;
         self.data_ctx.clear();
-        self.module.finalize_definitions();
+        self.finalize();
         let buffer = self.module.get_finalized_data(id);
         // TODO: Can we move the unsafe into cranelift?
         Ok(unsafe { slice::from_raw_parts(buffer.0, buffer.1) })
     }
 
     // Translate from toy-language AST nodes into Cranelift IR.
-    fn translate(
-        &mut self,
-        params: Vec<String>,
-        the_return: String,
-        stmts: Vec<Expr>,
-    ) -> Result<(), String> {
-        // Our toy language currently only supports I64 values, though Cranelift
-        // supports other types.
+    fn translate(&mut self, function: Function) -> Result<Translation, String> {
+        // Our toy language's values are integers unless they're known to be
+        // something else, though Cranelift supports other types. It currently
+        // only supports one return value, though Cranelift is designed to
+        // support more.
         let int = self.module.target_config().pointer_type();
-
-        for _p in &params {
-            self.ctx.func.signature.params.push(AbiParam::new(int));
+        let types = analysis::variable_types(&function.types, &function.stmts);
+        self.ctx.func.signature = self.signature(&function, &types);
+
+        let Function {
+            name,
+            params,
+            the_return,
+            mut stmts,
+            ..
+        } = function;
+
+        // Check the labels before building anything, so that there's nothing
+        // to clear up if they're wrong.
+        analysis::labels(&stmts)?;
+
+        // `*p` reads and writes integers, so only integer variables, and
+        // bools, which are integers too, can have their address taken.
+        let addressed = analysis::address_taken(&stmts);
+        for name in &addressed {
+            if let Some(&ty) = types.get(name) {
+                if ty != ValueType::Int && ty != ValueType::Bool {
+                    return Err(format!(
+                        "cannot take the address of `{}`, which is of type {}",
+                        name, ty
+                    ));
+                }
+            }
         }
 
-        // Our toy language currently only supports one return value, though
-        // Cranelift is designed to support more.
-        self.ctx.func.signature.returns.push(AbiParam::new(int));
-
         // Create the builder to builder a function.
         let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
 
@@ -146,34 +1063,187 @@ impl JIT {
         // predecessors.
         builder.seal_block(entry_ebb);
 
+        let mut hook_ebbs = HashSet::new();
+        if let Some(ref hook) = self.hook {
+            call_hook(hook, HookPoint::Entry, &mut builder, &mut hook_ebbs);
+        }
+
         // The toy language allows variables to be declared implicitly.
         // Walk the AST and declare all implicitly-declared variables.
-        let variables =
-            declare_variables(int, &mut builder, &params, &the_return, &stmts, entry_ebb);
+        let variables = declare_variables(
+            int,
+            &types,
+            &mut builder,
+            &params,
+            &the_return,
+            &stmts,
+            entry_ebb,
+        );
 
         // Now translate the statements of the function body.
         let mut trans = FunctionTranslator {
             int,
             builder,
+            next_variable: variables.len(),
+            peak_variables: variables.len(),
             variables,
+            types,
+            slots: HashMap::new(),
+            constants: &self.constants,
+            functions: &self.functions,
+            host_functions: &self.host_functions,
+            aliases: &self.aliases,
+            rhs_callback: self.rhs_callback,
+            overflow_handler: self.overflow_handler,
+            checked_negation: self.checked_negation,
+            inlining: vec![name.clone()],
+            loops: Vec::new(),
+            labels: HashMap::new(),
+            callees: Vec::new(),
+            strings: Vec::new(),
+            seals: HashMap::new(),
+            hook_ebbs,
+            check_sealing: self.check_sealing,
+            error: None,
             module: &mut self.module,
         };
+        trans.seals.insert(entry_ebb, 1);
+        trans.labels = trans.create_label_blocks(&stmts);
+        trans.slots = trans.spill_variables(&addressed);
+
+        // When the last statement assigns an if-else to the return variable,
+        // each arm can return its own value, rather than jumping to a merge
+        // block which does nothing but return it.
+        let tail_if_else = match stmts.last() {
+            Some(Expr::Assign(name, value)) => {
+                *name == the_return && matches!(**value, Expr::IfElse(..))
+            }
+            _ => false,
+        };
+        let tail = if tail_if_else { stmts.pop() } else { None };
+
         for expr in stmts {
-            trans.translate_expr(expr);
+            trans.translate_stmt(expr);
+        }
+
+        let hook = self.hook.as_deref();
+        match tail {
+            Some(tail) => {
+                let to = trans.variable_type(&the_return);
+                trans.translate_return(tail, to, hook)
+            }
+            None => {
+                // Set up the return variable of the function. Above, we
+                // declared a variable to hold the return value. Here, we just
+                // do a use of that variable.
+                let return_value = trans.use_variable(&the_return);
+
+                if let Some(hook) = hook {
+                    call_hook(
+                        hook,
+                        HookPoint::Exit,
+                        &mut trans.builder,
+                        &mut trans.hook_ebbs,
+                    );
+                }
+
+                // Emit the return instruction.
+                trans.builder.ins().return_(&[return_value]);
+            }
         }
+        trans.seal_label_blocks();
 
-        // Set up the return variable of the function. Above, we declared a
-        // variable to hold the return value. Here, we just do a use of that
-        // variable.
-        let return_variable = trans.variables.get(&the_return).unwrap();
-        let return_value = trans.builder.use_var(*return_variable);
+        if let Some(err) = trans.error.take() {
+            trans.builder.finalize();
+            self.module.clear_context(&mut self.ctx);
+            return Err(err);
+        }
 
-        // Emit the return instruction.
-        trans.builder.ins().return_(&[return_value]);
+        if self.check_sealing {
+            let problems = trans.sealing_problems();
+            if !problems.is_empty() {
+                trans.builder.finalize();
+                self.module.clear_context(&mut self.ctx);
+                return Err(format!(
+                    "the EBBs of `{}` weren't sealed properly: {}",
+                    name,
+                    problems.join(", ")
+                ));
+            }
+        }
 
         // Tell the builder we're done with this function.
         trans.builder.finalize();
-        Ok(())
+        let variables = trans.variables;
+        let callees = trans.callees;
+        let strings = trans.strings;
+        let stats = CompileStats {
+            peak_variables: trans.peak_variables,
+            instructions: self.ctx.func.dfg.num_insts(),
+            ebbs: self.ctx.func.layout.ebbs().count(),
+        };
+
+        if let Some(max_ebbs) = self.max_ebbs {
+            let ebbs = stats.ebbs;
+            if ebbs > max_ebbs {
+                self.module.clear_context(&mut self.ctx);
+                return Err(format!(
+                    "function `{}` has {} EBBs, but at most {} are allowed",
+                    name, ebbs, max_ebbs
+                ));
+            }
+        }
+        Ok(Translation {
+            variables,
+            stats,
+            callees,
+            strings,
+        })
+    }
+}
+
+/// A compiled function, along with the number of arguments it takes, so that
+/// it can be called with a slice of arguments. Its parameters and its result
+/// are all pointer-sized integers.
+pub struct DynFunc {
+    code: *const u8,
+    arity: usize,
+}
+
+impl DynFunc {
+    /// The most parameters a function called this way may have.
+    const MAX_ARITY: usize = 6;
+
+    /// Call the function, if `args` has the right number of arguments for it.
+    pub fn call(&self, args: &[isize]) -> Result<isize, String> {
+        if args.len() != self.arity {
+            return Err(format!(
+                "function takes {} but {} were given",
+                arguments(self.arity),
+                args.len()
+            ));
+        }
+
+        // Now that the arity is known to match, pick the function pointer type
+        // to call it through.
+        type I = isize;
+        let code = self.code;
+        Ok(unsafe {
+            match *args {
+                [] => mem::transmute::<*const u8, fn() -> I>(code)(),
+                [a] => mem::transmute::<*const u8, fn(I) -> I>(code)(a),
+                [a, b] => mem::transmute::<*const u8, fn(I, I) -> I>(code)(a, b),
+                [a, b, c] => mem::transmute::<*const u8, fn(I, I, I) -> I>(code)(a, b, c),
+                [a, b, c, d] => mem::transmute::<*const u8, fn(I, I, I, I) -> I>(code)(a, b, c, d),
+                [a, b, c, d, e] => {
+                    mem::transmute::<*const u8, fn(I, I, I, I, I) -> I>(code)(a, b, c, d, e)
+                }
+                [a, b, c, d, e, f] => {
+                    mem::transmute::<*const u8, fn(I, I, I, I, I, I) -> I>(code)(a, b, c, d, e, f)
+                }
+                _ => unreachable!(),
+            }
+        })
     }
 }
 
@@ -183,6 +1253,42 @@ struct FunctionTranslator<'a> {
     int: types::Type,
     builder: FunctionBuilder<'a>,
     variables: HashMap<String, Variable>,
+    /// The index to give the next `Variable` declared.
+    next_variable: usize,
+    /// The most variables there have been in `variables`.
+    peak_variables: usize,
+    /// The types of the variables which don't hold integers.
+    types: HashMap<String, ValueType>,
+    /// The stack slot of each variable whose address is taken, which is
+    /// read and written there instead of through its `Variable`.
+    slots: HashMap<String, StackSlot>,
+    constants: &'a HashMap<String, i64>,
+    functions: &'a HashMap<String, Function>,
+    host_functions: &'a HashMap<String, HostFunction>,
+    aliases: &'a HashMap<String, String>,
+    rhs_callback: Option<*const u8>,
+    overflow_handler: Option<*const u8>,
+    checked_negation: bool,
+    /// The function being translated, followed by the functions currently
+    /// being inlined into it, innermost last.
+    inlining: Vec<String>,
+    /// The block each enclosing loop goes on to on a `continue`, once one has
+    /// been needed, and the one it exits to on a `break`, innermost last.
+    loops: Vec<(Option<Ebb>, Ebb)>,
+    /// The block for each label in the function being translated.
+    labels: HashMap<String, Ebb>,
+    /// The functions called through a relocation so far.
+    callees: Vec<String>,
+    /// The data object declared for each string literal so far.
+    strings: Vec<(DataId, String)>,
+    /// The number of times each EBB has been sealed.
+    seals: HashMap<Ebb, usize>,
+    /// The EBBs hooks created, which it's up to them to seal.
+    hook_ebbs: HashSet<Ebb>,
+    check_sealing: bool,
+    /// The first mistake found in the function, which makes its translation
+    /// fail once it's finished.
+    error: Option<String>,
     module: &'a mut Module<SimpleJITBackend>,
 }
 
@@ -191,105 +1297,238 @@ impl<'a> FunctionTranslator<'a> {
     /// can then use these references in other instructions.
     fn translate_expr(&mut self, expr: Expr) -> Value {
         match expr {
-            Expr::Literal(literal) => {
-                let imm: i32 = literal.parse().unwrap();
-                self.builder.ins().iconst(self.int, i64::from(imm))
+            Expr::Literal(literal, radix) => {
+                // The parser only accepts literals which fit.
+                let imm = i64::from_str_radix(&literal, radix).unwrap();
+                self.builder.ins().iconst(self.int, imm)
             }
 
-            Expr::Add(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                self.builder.ins().iadd(lhs, rhs)
+            Expr::Float(digits) => {
+                let imm = digits.parse::<f64>().unwrap();
+                self.builder.ins().f64const(Ieee64::with_float(imm))
             }
 
-            Expr::Sub(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                self.builder.ins().isub(lhs, rhs)
+            // Arithmetic on `f64`s uses the floating-point instructions, and
+            // never overflows, and neither does arithmetic on unsigned
+            // integers, which wraps. Mixing `f64`s with integers is left for
+            // the verifier to reject.
+            Expr::Add(lhs, rhs) => {
+                if self.is_pointer(&lhs) || self.is_pointer(&rhs) {
+                    return self.translate_pointer_arithmetic(*lhs, *rhs, false);
+                }
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if self.is_float(lhs) {
+                    return self.builder.ins().fadd(lhs, rhs);
+                }
+                let result = self.builder.ins().iadd(lhs, rhs);
+                if unsigned {
+                    return result;
+                }
+                self.check_overflow(OVERFLOW_ADD, lhs, rhs, result)
+            }
+
+            Expr::Sub(lhs, rhs) => {
+                if self.is_pointer(&lhs) {
+                    return self.translate_pointer_arithmetic(*lhs, *rhs, true);
+                }
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if self.is_float(lhs) {
+                    return self.builder.ins().fsub(lhs, rhs);
+                }
+                let result = self.builder.ins().isub(lhs, rhs);
+                if unsigned {
+                    return result;
+                }
+                self.check_overflow(OVERFLOW_SUB, lhs, rhs, result)
             }
 
             Expr::Mul(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                self.builder.ins().imul(lhs, rhs)
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if self.is_float(lhs) {
+                    return self.builder.ins().fmul(lhs, rhs);
+                }
+                let result = self.builder.ins().imul(lhs, rhs);
+                if unsigned {
+                    return result;
+                }
+                self.check_overflow(OVERFLOW_MUL, lhs, rhs, result)
             }
 
+            // Division of signed integers rounds towards zero, and traps if
+            // the quotient overflows. Unsigned integers are divided as
+            // unsigned.
             Expr::Div(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                self.builder.ins().udiv(lhs, rhs)
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if self.is_float(lhs) {
+                    return self.builder.ins().fdiv(lhs, rhs);
+                }
+                if unsigned {
+                    return self.builder.ins().udiv(lhs, rhs);
+                }
+                self.builder.ins().sdiv(lhs, rhs)
             }
 
-            Expr::Eq(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self.builder.ins().icmp(IntCC::Equal, lhs, rhs);
-                self.builder.ins().bint(self.int, c)
+            Expr::Mod(lhs, rhs) => {
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if self.is_float(lhs) {
+                    return self.translate_float_rem(lhs, rhs);
+                }
+                if unsigned {
+                    return self.builder.ins().urem(lhs, rhs);
+                }
+                self.builder.ins().srem(lhs, rhs)
             }
 
-            Expr::Ne(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self.builder.ins().icmp(IntCC::NotEqual, lhs, rhs);
-                self.builder.ins().bint(self.int, c)
+            Expr::BitAnd(lhs, rhs) => {
+                let (lhs, rhs, _) = self.translate_operands(*lhs, *rhs);
+                self.builder.ins().band(lhs, rhs)
             }
 
-            Expr::Lt(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self.builder.ins().icmp(IntCC::SignedLessThan, lhs, rhs);
-                self.builder.ins().bint(self.int, c)
+            Expr::BitOr(lhs, rhs) => {
+                let (lhs, rhs, _) = self.translate_operands(*lhs, *rhs);
+                self.builder.ins().bor(lhs, rhs)
             }
 
-            Expr::Le(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self
-                    .builder
-                    .ins()
-                    .icmp(IntCC::SignedLessThanOrEqual, lhs, rhs);
-                self.builder.ins().bint(self.int, c)
+            Expr::BitXor(lhs, rhs) => {
+                let (lhs, rhs, _) = self.translate_operands(*lhs, *rhs);
+                self.builder.ins().bxor(lhs, rhs)
             }
 
-            Expr::Gt(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self.builder.ins().icmp(IntCC::SignedGreaterThan, lhs, rhs);
-                self.builder.ins().bint(self.int, c)
+            Expr::Shl(lhs, rhs) => {
+                let (lhs, rhs, _) = self.translate_operands(*lhs, *rhs);
+                self.builder.ins().ishl(lhs, rhs)
             }
 
-            Expr::Ge(lhs, rhs) => {
-                let lhs = self.translate_expr(*lhs);
-                let rhs = self.translate_expr(*rhs);
-                let c = self
-                    .builder
-                    .ins()
-                    .icmp(IntCC::SignedGreaterThanOrEqual, lhs, rhs);
+            // `>>` on an unsigned integer shifts in zeros, as `>>>` does.
+            Expr::Shr(lhs, rhs) => {
+                let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+                if unsigned {
+                    return self.builder.ins().ushr(lhs, rhs);
+                }
+                self.builder.ins().sshr(lhs, rhs)
+            }
+
+            Expr::UShr(lhs, rhs) => {
+                let (lhs, rhs, _) = self.translate_operands(*lhs, *rhs);
+                self.builder.ins().ushr(lhs, rhs)
+            }
+
+            expr @ Expr::Eq(..)
+            | expr @ Expr::Ne(..)
+            | expr @ Expr::Lt(..)
+            | expr @ Expr::Le(..)
+            | expr @ Expr::Gt(..)
+            | expr @ Expr::Ge(..)
+            | expr @ Expr::Not(_) => {
+                let c = self.translate_bool(expr);
                 self.builder.ins().bint(self.int, c)
             }
 
-            Expr::Call(name, args) => self.translate_call(name, args),
+            Expr::Bool(value) => self.builder.ins().iconst(self.int, value as i64),
+
+            Expr::And(lhs, rhs) => self.translate_short_circuit(*lhs, *rhs, true),
+            Expr::Or(lhs, rhs) => self.translate_short_circuit(*lhs, *rhs, false),
+
+            Expr::Call(name, args) => match self.translate_call(name, args) {
+                Ok(value) => value,
+                Err(msg) => self.fail(msg),
+            },
+
+            Expr::GlobalDataAddr(name) => match self.slots.get(&name) {
+                Some(&slot) => self.builder.ins().stack_addr(self.int, slot, 0),
+                None => self.translate_global_data_addr(name),
+            },
+
+            Expr::Str(contents) => self.translate_string(contents),
+
+            Expr::Neg(expr) => {
+                let unsigned = self.is_unsigned(&expr);
+                let value = self.translate_expr(*expr);
+                if self.is_float(value) {
+                    return self.builder.ins().fneg(value);
+                }
+                if self.checked_negation && !unsigned {
+                    // The most negative integer has no positive counterpart.
+                    let bits = self.builder.func.dfg.value_type(value).bits();
+                    let int_min = i64::MIN >> (64 - u32::from(bits));
+                    let overflows = self.builder.ins().icmp_imm(IntCC::Equal, value, int_min);
+                    self.builder
+                        .ins()
+                        .trapnz(overflows, TrapCode::IntegerOverflow);
+                }
+                self.builder.ins().irsub_imm(value, 0)
+            }
+
+            Expr::Cast(expr, ty) => {
+                let from = analysis::type_of(&expr, &self.types);
+                let value = self.translate_expr(*expr);
+                self.convert(value, from, ty)
+            }
+
+            Expr::Index(array, index) => self.translate_load_at(*array, *index),
 
-            Expr::GlobalDataAddr(name) => self.translate_global_data_addr(name),
+            Expr::IndexAssign(array, index, value) => {
+                self.translate_store_at(*array, *index, *value)
+            }
+
+            Expr::TypeOf(expr) => {
+                // The type is known without evaluating the operand, which
+                // isn't evaluated at all.
+                let tag = analysis::type_of(&expr, &self.types).tag();
+                self.builder.ins().iconst(self.int, tag)
+            }
 
             Expr::Identifier(name) => {
-                // `use_var` is used to read the value of a variable.
-                let variable = self.variables.get(&name).expect("variable not defined");
-                self.builder.use_var(*variable)
+                // `use_var` is used to read the value of a variable. Names
+                // which aren't variables may be host-provided constants, which
+                // are just materialized in place.
+                if self.variables.contains_key(&name) {
+                    self.use_variable(&name)
+                } else {
+                    let value = *self.constants.get(&name).expect("variable not defined");
+                    self.builder.ins().iconst(self.int, value)
+                }
             }
 
             Expr::Assign(name, expr) => {
                 // `def_var` is used to write the value of a variable. Note that
                 // variables can have multiple definitions. Cranelift will
                 // convert them into SSA form for itself automatically.
+                // A value of another type is converted to the variable's, as
+                // with `as`.
+                let from = analysis::type_of(&expr, &self.types);
                 let new_value = self.translate_expr(*expr);
-                let variable = self.variables.get(&name).unwrap();
-                self.builder.def_var(*variable, new_value);
+                let to = self.variable_type(&name);
+                let new_value = self.convert(new_value, from, to);
+                self.def_variable(&name, new_value);
                 new_value
             }
 
+            Expr::Deref(pointer) => {
+                let addr = self.translate_int_arg(*pointer);
+                self.builder.ins().load(self.int, MemFlags::new(), addr, 0)
+            }
+
+            Expr::DerefAssign(pointer, value) => {
+                let addr = self.translate_int_arg(*pointer);
+                let value = self.translate_int_arg(*value);
+                self.builder.ins().store(MemFlags::new(), value, addr, 0);
+                value
+            }
+
             Expr::IfElse(condition, then_body, else_body) => {
-                let condition_value = self.translate_expr(*condition);
+                // When the condition is a constant, only the arm it picks can
+                // run, so there's nothing to test, and no other arm to build.
+                if let Some(condition) = analysis::constant_value(&condition, self.constants) {
+                    let body = if condition != 0 { then_body } else { else_body };
+                    let mut value = self.builder.ins().iconst(self.int, 0);
+                    for expr in body {
+                        value = self.translate_expr(expr);
+                    }
+                    return value;
+                }
+
+                let condition_value = self.translate_condition(*condition);
 
                 let else_block = self.builder.create_ebb();
                 let merge_block = self.builder.create_ebb();
@@ -298,8 +1537,17 @@ impl<'a> FunctionTranslator<'a> {
                 // In traditional SSA form, this would produce a PHI between
                 // the then and else bodies. Cranelift uses block parameters,
                 // so set up a parameter in the merge block, and we'll pass
-                // the return values to it from the branches.
-                self.builder.append_ebb_param(merge_block, self.int);
+                // the return values to it from the branches. It's a float only
+                // if both bodies produce the same kind; otherwise, a body
+                // whose value is of the wrong type passes zero instead.
+                let then_type = analysis::body_type(&then_body, &self.types);
+                let else_type = analysis::body_type(&else_body, &self.types);
+                let return_type = if then_type == else_type {
+                    cranelift_type(self.int, then_type)
+                } else {
+                    self.int
+                };
+                self.builder.append_ebb_param(merge_block, return_type);
 
                 // Test the if condition and conditionally branch.
                 self.builder.ins().brz(condition_value, else_block, &[]);
@@ -308,16 +1556,20 @@ impl<'a> FunctionTranslator<'a> {
                 for expr in then_body {
                     then_return = self.translate_expr(expr);
                 }
+                let then_return =
+                    self.zero_unless(then_return, return_type, then_type.is_unsigned());
 
                 // Jump to the merge block, passing it the block return value.
                 self.builder.ins().jump(merge_block, &[then_return]);
 
                 self.builder.switch_to_block(else_block);
-                self.builder.seal_block(else_block);
+                self.seal_block(else_block);
                 let mut else_return = self.builder.ins().iconst(self.int, 0);
                 for expr in else_body {
                     else_return = self.translate_expr(expr);
                 }
+                let else_return =
+                    self.zero_unless(else_return, return_type, else_type.is_unsigned());
 
                 // Jump to the merge block, passing it the block return value.
                 self.builder.ins().jump(merge_block, &[else_return]);
@@ -326,7 +1578,7 @@ impl<'a> FunctionTranslator<'a> {
                 self.builder.switch_to_block(merge_block);
 
                 // We've now seen all the predecessors of the merge block.
-                self.builder.seal_block(merge_block);
+                self.seal_block(merge_block);
 
                 // Read the value of the if-else by reading the merge block
                 // parameter.
@@ -335,59 +1587,1288 @@ impl<'a> FunctionTranslator<'a> {
                 phi
             }
 
-            Expr::WhileLoop(condition, loop_body) => {
+            Expr::WhileLoop(condition, loop_body, else_body) => {
+                // A constant condition is folded: if it's false, only the
+                // else-body runs, and if it's true, the loop is only left by
+                // a `break`, so the else-body never runs.
+                let constant = analysis::constant_value(&condition, self.constants);
+                if constant == Some(0) {
+                    for expr in else_body {
+                        self.translate_stmt(expr);
+                    }
+                    return self.builder.ins().iconst(self.int, 0);
+                }
+
                 let header_block = self.builder.create_ebb();
+                let else_block = self.builder.create_ebb();
                 let exit_block = self.builder.create_ebb();
                 self.builder.ins().jump(header_block, &[]);
                 self.builder.switch_to_block(header_block);
 
-                let condition_value = self.translate_expr(*condition);
-                self.builder.ins().brz(condition_value, exit_block, &[]);
+                if constant.is_none() {
+                    let condition_value = self.translate_condition(*condition);
+                    self.builder.ins().brz(condition_value, else_block, &[]);
+                }
 
+                // A `break` in the body jumps straight to the exit block,
+                // skipping the else-body.
+                // A `continue` jumps back to the header, to test the
+                // condition again.
+                self.loops.push((Some(header_block), exit_block));
                 for expr in loop_body {
-                    self.translate_expr(expr);
+                    self.translate_stmt(expr);
                 }
+                self.loops.pop();
                 self.builder.ins().jump(header_block, &[]);
 
-                self.builder.switch_to_block(exit_block);
-
                 // We've reached the bottom of the loop, so there will be no
-                // more backedges to the header to exits to the bottom.
-                self.builder.seal_block(header_block);
-                self.builder.seal_block(exit_block);
+                // more backedges to the header or branches to the else-body.
+                self.seal_block(header_block);
+                if constant.is_none() {
+                    self.builder.switch_to_block(else_block);
+                    self.seal_block(else_block);
+                    for expr in else_body {
+                        self.translate_stmt(expr);
+                    }
+                    self.builder.ins().jump(exit_block, &[]);
+                }
+
+                self.builder.switch_to_block(exit_block);
+                self.seal_block(exit_block);
 
                 // Just return 0 for now.
                 self.builder.ins().iconst(self.int, 0)
             }
+
+            Expr::DoWhile(loop_body, condition) => {
+                // The body gets a block of its own, which the condition at
+                // the bottom branches back to.
+                let body_block = self.builder.create_ebb();
+                let exit_block = self.builder.create_ebb();
+                self.builder.ins().jump(body_block, &[]);
+                self.builder.switch_to_block(body_block);
+
+                self.loops.push((None, exit_block));
+                for expr in loop_body {
+                    self.translate_stmt(expr);
+                }
+                self.end_loop_body();
+                let condition_value = self.translate_condition(*condition);
+                self.builder.ins().brnz(condition_value, body_block, &[]);
+                self.builder.ins().jump(exit_block, &[]);
+
+                // The backedge was the last branch to the body, so only now
+                // can its block be sealed.
+                self.seal_block(body_block);
+                self.builder.switch_to_block(exit_block);
+                self.seal_block(exit_block);
+                self.builder.ins().iconst(self.int, 0)
+            }
+
+            Expr::Repeat(count, loop_body) => self.translate_repeat(*count, loop_body),
+
+            Expr::Break => match self.loops.last() {
+                Some(&(_, exit_block)) => self.jump_away(exit_block),
+                None => self.fail("`break` outside of a loop".to_string()),
+            },
+
+            Expr::Continue => match self.continue_block() {
+                Some(next_block) => self.jump_away(next_block),
+                None => self.fail("`continue` outside of a loop".to_string()),
+            },
+
+            Expr::Label(name) => {
+                // Fall through into the label's block, which `goto`s may also
+                // jump to from anywhere in the function.
+                let label_block = self.labels[&name];
+                self.builder.ins().jump(label_block, &[]);
+                self.builder.switch_to_block(label_block);
+                self.builder.ins().iconst(self.int, 0)
+            }
+
+            Expr::Goto(name) => {
+                let label_block = self.labels[&name];
+                self.jump_away(label_block)
+            }
         }
     }
 
-    fn translate_call(&mut self, name: String, args: Vec<Expr>) -> Value {
-        let mut sig = self.module.make_signature();
+    /// Translate an expression whose value isn't used. An `if` without an
+    /// `else` then needs neither an else block nor a parameter for its value
+    /// in the merge block, which the branch goes straight to when the
+    /// condition is false.
+    fn translate_stmt(&mut self, expr: Expr) {
+        match expr {
+            Expr::IfElse(condition, then_body, else_body)
+                if else_body.is_empty()
+                    && analysis::constant_value(&condition, self.constants).is_none() =>
+            {
+                let condition_value = self.translate_condition(*condition);
+                let merge_block = self.builder.create_ebb();
+                self.builder.ins().brz(condition_value, merge_block, &[]);
+                for expr in then_body {
+                    self.translate_stmt(expr);
+                }
+                self.builder.ins().jump(merge_block, &[]);
+                self.builder.switch_to_block(merge_block);
+                self.seal_block(merge_block);
+            }
+            expr => {
+                self.translate_expr(expr);
+            }
+        }
+    }
 
-        // Add a parameter for each argument.
-        for _arg in &args {
-            sig.params.push(AbiParam::new(self.int));
+    /// Translate `repeat count { loop_body }`. If the count is a small
+    /// constant, the body is unrolled that many times. Otherwise, it becomes a
+    /// loop which counts down, with the number of iterations left passed to
+    /// the loop header as a block parameter.
+    fn translate_repeat(&mut self, count: Expr, loop_body: Vec<Expr>) -> Value {
+        // Either way, a `break` leaves through the exit block.
+        let exit_block = self.builder.create_ebb();
+        match analysis::constant_value(&count, self.constants) {
+            Some(count) if count <= REPEAT_UNROLL_LIMIT => {
+                // A `continue` skips to the next copy of the body.
+                for _ in 0..count {
+                    self.loops.push((None, exit_block));
+                    for expr in loop_body.clone() {
+                        self.translate_stmt(expr);
+                    }
+                    self.end_loop_body();
+                }
+                self.builder.ins().jump(exit_block, &[]);
+            }
+            _ => {
+                let unsigned = self.is_unsigned(&count);
+                let count_value = self.translate_expr(count);
+                let count_value = self.fit_int(count_value, self.int, unsigned);
+                let header_block = self.builder.create_ebb();
+                self.builder.append_ebb_param(header_block, self.int);
+                self.builder.ins().jump(header_block, &[count_value]);
+                self.builder.switch_to_block(header_block);
+
+                let remaining = self.builder.ebb_params(header_block)[0];
+                let done = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThanOrEqual, remaining, 0);
+                self.builder.ins().brnz(done, exit_block, &[]);
+
+                // A `continue` skips to where the iteration is counted,
+                // before going back to the header.
+                self.loops.push((None, exit_block));
+                for expr in loop_body {
+                    self.translate_stmt(expr);
+                }
+                self.end_loop_body();
+                let remaining = self.builder.ins().iadd_imm(remaining, -1);
+                self.builder.ins().jump(header_block, &[remaining]);
+                self.seal_block(header_block);
+            }
         }
+        self.builder.switch_to_block(exit_block);
+        self.seal_block(exit_block);
+        self.builder.ins().iconst(self.int, 0)
+    }
 
-        // For simplicity for now, just make all calls return a single I64.
+    /// The block a `continue` in the innermost loop goes on to, which is
+    /// created when the first one needs it, or `None` outside of any loop.
+    fn continue_block(&mut self) -> Option<Ebb> {
+        let next_block = self.loops.last()?.0;
+        match next_block {
+            Some(next_block) => Some(next_block),
+            None => {
+                let next_block = self.builder.create_ebb();
+                self.loops.last_mut().unwrap().0 = Some(next_block);
+                Some(next_block)
+            }
+        }
+    }
+
+    /// Leave the body of the innermost loop, falling through into the block
+    /// its `continue`s go on to, if it has any.
+    fn end_loop_body(&mut self) {
+        let (next_block, _) = self.loops.pop().unwrap();
+        if let Some(next_block) = next_block {
+            self.builder.ins().jump(next_block, &[]);
+            self.builder.switch_to_block(next_block);
+            self.seal_block(next_block);
+        }
+    }
+
+    /// Jump unconditionally to `ebb`. Anything translated afterwards is
+    /// unreachable, but still needs a block to go in, so this switches to a
+    /// new one.
+    fn jump_away(&mut self, ebb: Ebb) -> Value {
+        self.builder.ins().jump(ebb, &[]);
+        let unreachable_block = self.builder.create_ebb();
+        self.builder.switch_to_block(unreachable_block);
+        self.seal_block(unreachable_block);
+        self.builder.ins().iconst(self.int, 0)
+    }
+
+    /// Translate `expr`, the last thing the function evaluates, and return
+    /// its value, converted to `to`, the type of the return variable. An
+    /// if-else returns from the end of each of its arms, so there's no need
+    /// for a merge block, and the same goes for any if-else at the end of
+    /// those arms.
+    fn translate_return(&mut self, expr: Expr, to: ValueType, hook: Option<&Hook>) {
+        match expr {
+            // Nothing reads the variable once the function has returned, so
+            // it only matters if it would convert the value.
+            Expr::Assign(name, value) => {
+                let ty = self.variable_type(&name);
+                if ty == to {
+                    self.translate_return(*value, to, hook)
+                } else {
+                    let value = self.translate_expr(Expr::Assign(name, value));
+                    self.return_value(value, ty, to, hook);
+                }
+            }
+
+            Expr::IfElse(condition, then_body, else_body) => {
+                if let Some(condition) = analysis::constant_value(&condition, self.constants) {
+                    let body = if condition != 0 { then_body } else { else_body };
+                    return self.translate_return_body(body, to, hook);
+                }
+
+                let condition_value = self.translate_condition(*condition);
+                let else_block = self.builder.create_ebb();
+                self.builder.ins().brz(condition_value, else_block, &[]);
+                self.translate_return_body(then_body, to, hook);
+
+                self.builder.switch_to_block(else_block);
+                self.seal_block(else_block);
+                self.translate_return_body(else_body, to, hook);
+            }
+
+            expr => {
+                let from = analysis::type_of(&expr, &self.types);
+                let value = self.translate_expr(expr);
+                self.return_value(value, from, to, hook);
+            }
+        }
+    }
+
+    /// Return `value`, of type `from`, converted to `to`, the type of the
+    /// return variable.
+    fn return_value(&mut self, value: Value, from: ValueType, to: ValueType, hook: Option<&Hook>) {
+        let value = self.convert(value, from, to);
+        if let Some(hook) = hook {
+            call_hook(
+                hook,
+                HookPoint::Exit,
+                &mut self.builder,
+                &mut self.hook_ebbs,
+            );
+        }
+        self.builder.ins().return_(&[value]);
+    }
+
+    /// Translate the statements of an if-else arm whose value is returned
+    /// from the function. Like any other block, an empty one has the value
+    /// zero.
+    fn translate_return_body(&mut self, mut body: Vec<Expr>, to: ValueType, hook: Option<&Hook>) {
+        let last = body
+            .pop()
+            .unwrap_or_else(|| Expr::Literal("0".to_string(), 10));
+        for expr in body {
+            self.translate_stmt(expr);
+        }
+        self.translate_return(last, to, hook);
+    }
+
+    /// Create a block for each of a function's labels. They can't be sealed
+    /// until the whole function has been translated, since a `goto` anywhere
+    /// in it may jump to them.
+    fn create_label_blocks(&mut self, stmts: &[Expr]) -> HashMap<String, Ebb> {
+        let mut labels = HashMap::new();
+        for stmt in stmts {
+            if let Expr::Label(ref name) = *stmt {
+                labels.insert(name.clone(), self.builder.create_ebb());
+            }
+        }
+        labels
+    }
+
+    fn seal_label_blocks(&mut self) {
+        let label_blocks: Vec<Ebb> = self.labels.values().cloned().collect();
+        for label_block in label_blocks {
+            self.seal_block(label_block);
+        }
+    }
+
+    /// Seal `ebb`, counting how many times it's been sealed.
+    fn seal_block(&mut self, ebb: Ebb) {
+        let seals = self.seals.entry(ebb).or_insert(0);
+        *seals += 1;
+
+        // Cranelift can't seal a block twice, so when sealing is being
+        // checked, a second seal is left for the check to report instead.
+        if *seals == 1 || !self.check_sealing {
+            self.builder.seal_block(ebb);
+        }
+    }
+
+    /// Describe each EBB of the translator's own which hasn't been sealed
+    /// exactly once, sealing the ones which haven't been sealed at all, so
+    /// that the builder can still be finalized.
+    fn sealing_problems(&mut self) -> Vec<String> {
+        let ebbs: Vec<Ebb> = self
+            .builder
+            .func
+            .layout
+            .ebbs()
+            .filter(|ebb| !self.hook_ebbs.contains(ebb))
+            .collect();
+        let mut problems = Vec::new();
+        for ebb in ebbs {
+            match self.seals.get(&ebb).cloned().unwrap_or(0) {
+                0 => {
+                    problems.push(format!("{} is never sealed", ebb));
+                    self.builder.seal_block(ebb);
+                }
+                1 => {}
+                seals => problems.push(format!("{} is sealed {} times", ebb, seals)),
+            }
+        }
+        problems
+    }
+
+    /// If there's an overflow handler, check whether `result`, the wrapped
+    /// result of the operation `op` on `lhs` and `rhs`, overflowed, and if so,
+    /// call the handler and use what it returns instead.
+    fn check_overflow(&mut self, op: isize, lhs: Value, rhs: Value, result: Value) -> Value {
+        let handler = match self.overflow_handler {
+            Some(handler) => handler,
+            None => return result,
+        };
+        let ty = self.builder.func.dfg.value_type(result);
+        let overflows = match op {
+            OVERFLOW_ADD | OVERFLOW_SUB => {
+                // Addition overflows when both operands have a different
+                // sign from the result. Subtraction overflows when the
+                // operands have different signs, and the result's sign is
+                // different from the left operand's.
+                let lhs_flipped = self.builder.ins().bxor(lhs, result);
+                let other = if op == OVERFLOW_ADD {
+                    self.builder.ins().bxor(rhs, result)
+                } else {
+                    self.builder.ins().bxor(lhs, rhs)
+                };
+                let both = self.builder.ins().band(lhs_flipped, other);
+                self.builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0)
+            }
+            _ => {
+                // Multiplication overflows when the high half of the full
+                // product isn't just the sign extension of the low half.
+                let high = self.builder.ins().smulhi(lhs, rhs);
+                let sign = self
+                    .builder
+                    .ins()
+                    .sshr_imm(result, i64::from(ty.bits()) - 1);
+                self.builder.ins().icmp(IntCC::NotEqual, high, sign)
+            }
+        };
+
+        let handler_block = self.builder.create_ebb();
+        let merge_block = self.builder.create_ebb();
+        self.builder.append_ebb_param(merge_block, ty);
+        self.builder.ins().brnz(overflows, handler_block, &[]);
+        self.builder.ins().jump(merge_block, &[result]);
+
+        self.builder.switch_to_block(handler_block);
+        self.seal_block(handler_block);
+        let mut sig = self.module.make_signature();
+        for _ in 0..3 {
+            sig.params.push(AbiParam::new(self.int));
+        }
         sig.returns.push(AbiParam::new(self.int));
+        let sig_ref = self.builder.import_signature(sig);
+        let callee = self.builder.ins().iconst(self.int, handler as i64);
+        // The handler is always given integers, so `i32`s are widened for
+        // it, and what it returns is truncated back.
+        let op = self.builder.ins().iconst(self.int, op as i64);
+        let lhs = self.fit_int(lhs, self.int, false);
+        let rhs = self.fit_int(rhs, self.int, false);
+        let call = self
+            .builder
+            .ins()
+            .call_indirect(sig_ref, callee, &[op, lhs, rhs]);
+        let handled = self.builder.inst_results(call)[0];
+        let handled = self.fit_int(handled, ty, false);
+        self.builder.ins().jump(merge_block, &[handled]);
+
+        self.builder.switch_to_block(merge_block);
+        self.seal_block(merge_block);
+        self.builder.ebb_params(merge_block)[0]
+    }
+
+    /// Translate a condition for `brz` or `brnz` to test. Comparisons, `!`,
+    /// `true` and `false` are left as `b1` values, rather than widened to
+    /// integers only to be tested against zero.
+    fn translate_condition(&mut self, expr: Expr) -> Value {
+        match expr {
+            expr @ Expr::Eq(..)
+            | expr @ Expr::Ne(..)
+            | expr @ Expr::Lt(..)
+            | expr @ Expr::Le(..)
+            | expr @ Expr::Gt(..)
+            | expr @ Expr::Ge(..)
+            | expr @ Expr::Not(_)
+            | expr @ Expr::Bool(_) => self.translate_bool(expr),
+            expr => self.translate_expr(expr),
+        }
+    }
+
+    /// Translate an expression to a `b1`, which is true if its value is
+    /// nonzero. Comparisons of unsigned integers are unsigned, and those of
+    /// `f64`s are ordered, so they're false when either operand is NaN, other
+    /// than `!=`.
+    fn translate_bool(&mut self, expr: Expr) -> Value {
+        let (cc, ucc, fcc, lhs, rhs) = match expr {
+            Expr::Eq(lhs, rhs) => (IntCC::Equal, IntCC::Equal, FloatCC::Equal, lhs, rhs),
+            Expr::Ne(lhs, rhs) => (
+                IntCC::NotEqual,
+                IntCC::NotEqual,
+                FloatCC::NotEqual,
+                lhs,
+                rhs,
+            ),
+            Expr::Lt(lhs, rhs) => (
+                IntCC::SignedLessThan,
+                IntCC::UnsignedLessThan,
+                FloatCC::LessThan,
+                lhs,
+                rhs,
+            ),
+            Expr::Le(lhs, rhs) => (
+                IntCC::SignedLessThanOrEqual,
+                IntCC::UnsignedLessThanOrEqual,
+                FloatCC::LessThanOrEqual,
+                lhs,
+                rhs,
+            ),
+            Expr::Gt(lhs, rhs) => (
+                IntCC::SignedGreaterThan,
+                IntCC::UnsignedGreaterThan,
+                FloatCC::GreaterThan,
+                lhs,
+                rhs,
+            ),
+            Expr::Ge(lhs, rhs) => (
+                IntCC::SignedGreaterThanOrEqual,
+                IntCC::UnsignedGreaterThanOrEqual,
+                FloatCC::GreaterThanOrEqual,
+                lhs,
+                rhs,
+            ),
+            Expr::Bool(value) => return self.builder.ins().bconst(types::B1, value),
+            Expr::Not(expr) => {
+                let value = self.translate_expr(*expr);
+                return self.builder.ins().icmp_imm(IntCC::Equal, value, 0);
+            }
+            expr => {
+                let value = self.translate_expr(expr);
+                return self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0);
+            }
+        };
+        let (lhs, rhs, unsigned) = self.translate_operands(*lhs, *rhs);
+        if self.is_float(lhs) {
+            self.builder.ins().fcmp(fcc, lhs, rhs)
+        } else if unsigned {
+            self.builder.ins().icmp(ucc, lhs, rhs)
+        } else {
+            self.builder.ins().icmp(cc, lhs, rhs)
+        }
+    }
+
+    /// `value` if it's of type `ty`, or an integer which can be converted to
+    /// it, and otherwise a zero of that type.
+    fn zero_unless(&mut self, value: Value, ty: Type, unsigned: bool) -> Value {
+        let value = self.fit_int(value, ty, unsigned);
+        if self.builder.func.dfg.value_type(value) == ty {
+            value
+        } else {
+            zero(&mut self.builder, ty)
+        }
+    }
+
+    /// Translate the operands of a binary operator, along with whether the
+    /// operation is unsigned. When they're integers of different widths, the
+    /// narrower one is extended to match.
+    fn translate_operands(&mut self, lhs: Expr, rhs: Expr) -> (Value, Value, bool) {
+        let lhs_type = analysis::type_of(&lhs, &self.types);
+        let rhs_type = analysis::type_of(&rhs, &self.types);
+        let ty = analysis::arithmetic_type(lhs_type, rhs_type);
+        let lhs = self.translate_expr(lhs);
+        let rhs = self.translate_expr(rhs);
+
+        // An integer mixed with a float is converted to a float, as is an
+        // `f32` mixed with an `f64`.
+        if ty == ValueType::F32 || ty == ValueType::F64 {
+            let lhs = self.convert(lhs, lhs_type, ty);
+            let rhs = self.convert(rhs, rhs_type, ty);
+            return (lhs, rhs, false);
+        }
+        let unsigned = ty.is_unsigned();
+        let lhs_bits = self.builder.func.dfg.value_type(lhs);
+        let rhs_bits = self.builder.func.dfg.value_type(rhs);
+        if lhs_bits.bits() < rhs_bits.bits() {
+            let lhs = self.fit_int(lhs, rhs_bits, lhs_type.is_unsigned());
+            (lhs, rhs, unsigned)
+        } else {
+            let rhs = self.fit_int(rhs, lhs_bits, rhs_type.is_unsigned());
+            (lhs, rhs, unsigned)
+        }
+    }
+
+    /// The type of the variable `name`, which is an integer unless it's known
+    /// to be something else.
+    fn variable_type(&self, name: &str) -> ValueType {
+        self.types.get(name).cloned().unwrap_or(ValueType::Int)
+    }
+
+    /// Whether `expr` produces an unsigned integer.
+    fn is_unsigned(&self, expr: &Expr) -> bool {
+        analysis::type_of(expr, &self.types).is_unsigned()
+    }
+
+    /// Whether `expr` produces a pointer.
+    fn is_pointer(&self, expr: &Expr) -> bool {
+        analysis::type_of(expr, &self.types) == ValueType::Ptr
+    }
+
+    /// `p + n`, `n + p` and `p - n` move the pointer `p` by `n` integers,
+    /// and `p - q` is the number of integers from `q` to `p`. Like unsigned
+    /// arithmetic, they wrap rather than checking for overflow.
+    fn translate_pointer_arithmetic(&mut self, lhs: Expr, rhs: Expr, subtract: bool) -> Value {
+        let lhs_pointer = self.is_pointer(&lhs);
+        let rhs_pointer = self.is_pointer(&rhs);
+        let (lhs, rhs, _) = self.translate_operands(lhs, rhs);
+        let bytes = i64::from(self.int.bytes());
+        if subtract && rhs_pointer {
+            let difference = self.builder.ins().isub(lhs, rhs);
+            return self.builder.ins().sdiv_imm(difference, bytes);
+        }
+        if !lhs_pointer {
+            let offset = self.builder.ins().imul_imm(lhs, bytes);
+            return self.builder.ins().iadd(offset, rhs);
+        }
+        let offset = self.builder.ins().imul_imm(rhs, bytes);
+        if subtract {
+            self.builder.ins().isub(lhs, offset)
+        } else {
+            self.builder.ins().iadd(lhs, offset)
+        }
+    }
+
+    /// Read the variable `name`, from its stack slot if its address is taken.
+    fn use_variable(&mut self, name: &str) -> Value {
+        match self.slots.get(name) {
+            Some(&slot) => self.builder.ins().stack_load(self.int, slot, 0),
+            None => self.builder.use_var(self.variables[name]),
+        }
+    }
+
+    /// Write `value` to the variable `name`, in its stack slot if its address
+    /// is taken.
+    fn def_variable(&mut self, name: &str, value: Value) {
+        match self.slots.get(name) {
+            Some(&slot) => {
+                self.builder.ins().stack_store(value, slot, 0);
+            }
+            None => self.builder.def_var(self.variables[name], value),
+        }
+    }
+
+    /// Give each of the variables among `names` a stack slot, holding the
+    /// value it has so far, so that its address can be taken. From then on,
+    /// it's read and written there. The other names are data objects.
+    fn spill_variables(&mut self, names: &[String]) -> HashMap<String, StackSlot> {
+        let mut slots = HashMap::new();
+        for name in names {
+            let variable = match self.variables.get(name) {
+                Some(&variable) => variable,
+                None => continue,
+            };
+            let slot = self.builder.create_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                self.int.bytes(),
+            ));
+            let value = self.builder.use_var(variable);
+            self.builder.ins().stack_store(value, slot, 0);
+            slots.insert(name.clone(), slot);
+        }
+        slots
+    }
 
-        // TODO: Streamline the API here?
+    /// `value` converted to the integer type `ty`, by extending or truncating
+    /// it, if it's an integer of another width. It's zero-extended if it's
+    /// `unsigned`, and sign-extended otherwise. Anything else is left alone.
+    fn fit_int(&mut self, value: Value, ty: Type, unsigned: bool) -> Value {
+        let from = self.builder.func.dfg.value_type(value);
+        if from == ty || from.is_float() || !ty.is_int() {
+            value
+        } else if from.bits() < ty.bits() && unsigned {
+            self.builder.ins().uextend(ty, value)
+        } else if from.bits() < ty.bits() {
+            self.builder.ins().sextend(ty, value)
+        } else {
+            self.builder.ins().ireduce(ty, value)
+        }
+    }
+
+    /// Whether `value` is an `f32` or an `f64`, rather than an integer.
+    fn is_float(&self, value: Value) -> bool {
+        self.builder.func.dfg.value_type(value).is_float()
+    }
+
+    /// Translate `lhs && rhs` if `is_and`, or `lhs || rhs` otherwise. Both
+    /// produce 0 or 1, and only evaluate `rhs` if `lhs` doesn't already
+    /// decide the result.
+    fn translate_short_circuit(&mut self, lhs: Expr, rhs: Expr, is_and: bool) -> Value {
+        let lhs_value = self.translate_condition(lhs);
+
+        // As with if-else, the result is passed to the merge block as a block
+        // parameter, from whichever way it's reached.
+        let merge_block = self.builder.create_ebb();
+        self.builder.append_ebb_param(merge_block, self.int);
+        let decided = self.builder.ins().iconst(self.int, !is_and as i64);
+        if is_and {
+            self.builder.ins().brz(lhs_value, merge_block, &[decided]);
+        } else {
+            self.builder.ins().brnz(lhs_value, merge_block, &[decided]);
+        }
+
+        if let Some(callback) = self.rhs_callback {
+            let sig = self.module.make_signature();
+            let sig_ref = self.builder.import_signature(sig);
+            let callee = self.builder.ins().iconst(self.int, callback as i64);
+            self.builder.ins().call_indirect(sig_ref, callee, &[]);
+        }
+        let c = self.translate_bool(rhs);
+        let rhs_value = self.builder.ins().bint(self.int, c);
+        self.builder.ins().jump(merge_block, &[rhs_value]);
+
+        self.builder.switch_to_block(merge_block);
+        self.seal_block(merge_block);
+        self.builder.ebb_params(merge_block)[0]
+    }
+
+    /// Record `msg` as the reason the function can't be translated, unless
+    /// an earlier mistake was found, and carry on with a placeholder value.
+    fn fail(&mut self, msg: String) -> Value {
+        if self.error.is_none() {
+            self.error = Some(msg);
+        }
+        self.builder.ins().iconst(self.int, 0)
+    }
+
+    fn translate_call(&mut self, name: String, args: Vec<Expr>) -> Result<Value, String> {
+        let name = match self.aliases.get(&name) {
+            Some(target) => target.clone(),
+            None => name,
+        };
+
+        match Builtin::lookup(&name) {
+            Some(Builtin::AssertEq) => return Ok(self.translate_assert_eq(args)),
+            Some(Builtin::Isqrt) => return Ok(self.translate_isqrt(args)),
+            Some(Builtin::Array) => return Ok(self.translate_array(args)),
+            Some(Builtin::Load) => return Ok(self.translate_load(args)),
+            Some(Builtin::Store) => return Ok(self.translate_store(args)),
+            Some(Builtin::Alloc) => return Ok(self.translate_alloc(args)),
+            Some(Builtin::Free) => return Ok(self.translate_free(args)),
+            Some(Builtin::Len) => {
+                let len = string_len(&args)?;
+                return Ok(self.builder.ins().iconst(self.int, len as i64));
+            }
+            Some(Builtin::F32) => {
+                return Ok(self.translate_conversion(&name, args, ValueType::F32))
+            }
+            Some(Builtin::F64) => {
+                return Ok(self.translate_conversion(&name, args, ValueType::F64))
+            }
+            Some(Builtin::I32) => {
+                return Ok(self.translate_conversion(&name, args, ValueType::I32))
+            }
+            Some(Builtin::I64) => {
+                return Ok(self.translate_conversion(&name, args, ValueType::Int))
+            }
+            None => {}
+        }
+
+        let functions = self.functions;
+        let args = match functions.get(&name) {
+            Some(callee) => callee.bind_arguments(args)?,
+            None => args,
+        };
+
+        // Calls to functions marked `#[inline]` are replaced by the callee's
+        // body, except for recursive calls, which would never end.
+        if let Some(callee) = functions.get(&name) {
+            if callee.inline == Inline::Always
+                && callee.params.len() == args.len()
+                && !self.inlining.contains(&name)
+            {
+                return Ok(self.translate_inline_call(callee, args));
+            }
+        }
+
+        let host_functions = self.host_functions;
+        if let Some(host) = host_functions.get(&name) {
+            return Ok(self.translate_host_call(&name, host, args));
+        }
+
+        // Functions which are already declared, including ones defined later in
+        // the same program, are called with the signature they were declared
+        // with.
+        if let Some(callee) = self.functions.get(&name) {
+            if callee.params.len() != args.len() {
+                return Err(format!(
+                    "`{}` takes {} but {} were given",
+                    name,
+                    arguments(callee.params.len()),
+                    args.len()
+                ));
+            }
+        }
+        let callee = match self.module.get_name(&name) {
+            Some(FuncOrDataId::Func(id)) => id,
+            // The host can't provide an operator, so it has to be defined.
+            _ if is_operator_function(&name) && !self.functions.contains_key(&name) => {
+                return Err(format!("`{}` is not defined", name));
+            }
+            _ => {
+                let mut sig = self.module.make_signature();
+
+                // Add a parameter for each argument.
+                for _arg in &args {
+                    sig.params.push(AbiParam::new(self.int));
+                }
+
+                // For simplicity for now, just make all calls return a single I64.
+                sig.returns.push(AbiParam::new(self.int));
+
+                // TODO: Streamline the API here?
+                self.module
+                    .declare_function(&name, Linkage::Import, &sig)
+                    .map_err(|e| e.to_string())?
+            }
+        };
+        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let unsigned_result = match self.functions.get(&name) {
+            Some(callee) => analysis::return_type(callee).is_unsigned(),
+            None => false,
+        };
+        if !self.callees.contains(&name) {
+            self.callees.push(name);
+        }
+
+        // Arguments are evaluated from left to right, so that side effects in
+        // them, such as assignments, happen in the order they're written.
+        // Each is converted to the type of its parameter, as an assignment
+        // would convert it, and an integer result to the toy language's
+        // integer type.
+        let sig_ref = self.builder.func.dfg.ext_funcs[local_callee].signature;
+        let param_types: Vec<Type> = self.builder.func.dfg.signatures[sig_ref]
+            .params
+            .iter()
+            .map(|param| param.value_type)
+            .collect();
+        let mut arg_values = Vec::new();
+        for (arg, ty) in args.into_iter().zip(param_types) {
+            let unsigned = self.is_unsigned(&arg);
+            let value = self.translate_expr(arg);
+            arg_values.push(self.convert_to(value, ty, unsigned));
+        }
+        let call = self.builder.ins().call(local_callee, &arg_values);
+        let result = self.builder.inst_results(call)[0];
+        Ok(self.fit_int(result, self.int, unsigned_result))
+    }
+
+    /// The conversions `f32(x)`, `f64(x)`, `i32(x)` and `i64(x)`, which
+    /// convert `x` to the type `ty`, just as `x as f32` and so on do.
+    fn translate_conversion(&mut self, name: &str, args: Vec<Expr>, ty: ValueType) -> Value {
+        if args.len() != 1 {
+            return self.fail(format!(
+                "`{}` takes 1 argument but {} were given",
+                name,
+                args.len()
+            ));
+        }
+        let arg = args.into_iter().next().unwrap();
+        let from = analysis::type_of(&arg, &self.types);
+        let value = self.translate_expr(arg);
+        self.convert(value, from, ty)
+    }
+
+    /// Convert `value`, of type `from`, to the type `to`. Converting between
+    /// float types rounds to the nearest `f32`, or is exact, and converting an
+    /// integer rounds to the nearest float. A float converted to an integer is
+    /// rounded towards zero, saturating if it's out of range, and integers are
+    /// converted by extending or truncating them. Converting between signed
+    /// and unsigned integers of the same width keeps their bits.
+    fn convert(&mut self, value: Value, from: ValueType, to: ValueType) -> Value {
+        let ty = cranelift_type(self.int, to);
+        let from_type = self.builder.func.dfg.value_type(value);
+        if from_type == ty {
+            value
+        } else if ty.is_int() {
+            if !from_type.is_float() {
+                self.fit_int(value, ty, from.is_unsigned())
+            } else if to.is_unsigned() {
+                self.builder.ins().fcvt_to_uint_sat(ty, value)
+            } else {
+                self.builder.ins().fcvt_to_sint_sat(ty, value)
+            }
+        } else if from_type == types::F32 {
+            self.builder.ins().fpromote(ty, value)
+        } else if from_type == types::F64 {
+            self.builder.ins().fdemote(ty, value)
+        } else if from.is_unsigned() {
+            self.builder.ins().fcvt_from_uint(ty, value)
+        } else {
+            self.builder.ins().fcvt_from_sint(ty, value)
+        }
+    }
+
+    /// `assert_eq(a, b)` traps with `ASSERT_EQ_TRAP` unless `a` and `b` are
+    /// equal, and otherwise evaluates to zero.
+    fn translate_assert_eq(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 2 {
+            return self.fail(format!(
+                "`assert_eq` takes 2 arguments but {} were given",
+                args.len()
+            ));
+        }
+        let mut args = args.into_iter();
+        let lhs = args.next().unwrap();
+        let rhs = args.next().unwrap();
+        let (lhs, rhs, _) = self.translate_operands(lhs, rhs);
+
+        let trap_block = self.builder.create_ebb();
+        let continue_block = self.builder.create_ebb();
+        let equal = self.builder.ins().icmp(IntCC::Equal, lhs, rhs);
+        self.builder.ins().brz(equal, trap_block, &[]);
+        self.builder.ins().jump(continue_block, &[]);
+
+        self.builder.switch_to_block(trap_block);
+        self.seal_block(trap_block);
+        self.builder.ins().trap(TrapCode::User(ASSERT_EQ_TRAP));
+
+        self.builder.switch_to_block(continue_block);
+        self.seal_block(continue_block);
+        self.builder.ins().iconst(self.int, 0)
+    }
+
+    /// `isqrt(x)` is the largest integer whose square is at most `x`. It's
+    /// computed with Newton's method: starting from `x`, each guess `g` is
+    /// improved to `(g + x / g) / 2`, until that stops getting smaller.
+    fn translate_isqrt(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 1 {
+            return self.fail(format!(
+                "`isqrt` takes 1 argument but {} were given",
+                args.len()
+            ));
+        }
+        let arg = args.into_iter().next().unwrap();
+        let unsigned = self.is_unsigned(&arg);
+        let x = self.translate_expr(arg);
+        let x = self.fit_int(x, self.int, unsigned);
+
+        let negative = self.builder.ins().icmp_imm(IntCC::SignedLessThan, x, 0);
+        self.builder
+            .ins()
+            .trapnz(negative, TrapCode::User(ISQRT_TRAP));
+
+        // 0 and 1 are their own square roots, and 0 can't be divided by.
+        let loop_block = self.builder.create_ebb();
+        let done_block = self.builder.create_ebb();
+        self.builder.append_ebb_param(loop_block, self.int);
+        self.builder.append_ebb_param(done_block, self.int);
+        let small = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, x, 2);
+        self.builder.ins().brnz(small, done_block, &[x]);
+        self.builder.ins().jump(loop_block, &[x]);
+
+        // Since `x` isn't negative, the arithmetic can be unsigned, which
+        // keeps `g + x / g` from overflowing.
+        self.builder.switch_to_block(loop_block);
+        let guess = self.builder.ebb_params(loop_block)[0];
+        let quotient = self.builder.ins().udiv(x, guess);
+        let sum = self.builder.ins().iadd(guess, quotient);
+        let next = self.builder.ins().ushr_imm(sum, 1);
+        let smaller = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedLessThan, next, guess);
+        self.builder.ins().brz(smaller, done_block, &[guess]);
+        self.builder.ins().jump(loop_block, &[next]);
+        self.seal_block(loop_block);
+
+        self.builder.switch_to_block(done_block);
+        self.seal_block(done_block);
+        self.builder.ebb_params(done_block)[0]
+    }
+
+    /// `array(n)` is the address of a stack slot holding `n` integers, which
+    /// lasts until the function returns. Each call has its own slot, which is
+    /// zeroed every time the call is evaluated. `n` must be a constant.
+    fn translate_array(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 1 {
+            return self.fail(format!(
+                "`array` takes 1 argument but {} were given",
+                args.len()
+            ));
+        }
+        let len = match analysis::constant_value(&args[0], self.constants) {
+            Some(len) if (0..=MAX_ARRAY_LEN).contains(&len) => len,
+            _ => {
+                return self.fail(format!(
+                    "the length of an array must be a constant from 0 to {}",
+                    MAX_ARRAY_LEN
+                ))
+            }
+        };
+        let bytes = i64::from(self.int.bytes());
+        let size = len * bytes;
+        let slot = self
+            .builder
+            .create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size as u32));
+        let addr = self.builder.ins().stack_addr(self.int, slot, 0);
+
+        let zero = self.builder.ins().iconst(self.int, 0);
+        if len <= ARRAY_UNROLL_LIMIT {
+            for offset in (0..size).step_by(bytes as usize) {
+                self.builder
+                    .ins()
+                    .store(MemFlags::new(), zero, addr, offset as i32);
+            }
+            return addr;
+        }
+
+        // Longer arrays are zeroed by a loop, which is passed the offset of
+        // the next element as a block parameter.
+        let loop_block = self.builder.create_ebb();
+        let done_block = self.builder.create_ebb();
+        self.builder.append_ebb_param(loop_block, self.int);
+        self.builder.ins().jump(loop_block, &[zero]);
+
+        self.builder.switch_to_block(loop_block);
+        let offset = self.builder.ebb_params(loop_block)[0];
+        let element = self.builder.ins().iadd(addr, offset);
+        self.builder.ins().store(MemFlags::new(), zero, element, 0);
+        let next = self.builder.ins().iadd_imm(offset, bytes);
+        let more = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedLessThan, next, size);
+        self.builder.ins().brnz(more, loop_block, &[next]);
+        self.builder.ins().jump(done_block, &[]);
+        self.seal_block(loop_block);
+
+        self.builder.switch_to_block(done_block);
+        self.seal_block(done_block);
+        addr
+    }
+
+    /// `load(a, i)` reads the `i`th integer from the address `a`. Nothing
+    /// checks that it's in bounds.
+    fn translate_load(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 2 {
+            return self.fail(format!(
+                "`load` takes 2 arguments but {} were given",
+                args.len()
+            ));
+        }
+        let mut args = args.into_iter();
+        self.translate_load_at(args.next().unwrap(), args.next().unwrap())
+    }
+
+    /// `store(a, i, x)` writes `x` as the `i`th integer from the address `a`,
+    /// and evaluates to `x`. Nothing checks that it's in bounds.
+    fn translate_store(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 3 {
+            return self.fail(format!(
+                "`store` takes 3 arguments but {} were given",
+                args.len()
+            ));
+        }
+        let mut args = args.into_iter();
+        let array = args.next().unwrap();
+        let index = args.next().unwrap();
+        self.translate_store_at(array, index, args.next().unwrap())
+    }
+
+    /// Read the `index`th integer from the address `array`, for `load` and
+    /// `array[index]`.
+    fn translate_load_at(&mut self, array: Expr, index: Expr) -> Value {
+        let addr = self.translate_element_addr(array, index);
+        self.builder.ins().load(self.int, MemFlags::new(), addr, 0)
+    }
+
+    /// Write `value` as the `index`th integer from the address `array`, for
+    /// `store` and `array[index] = value`.
+    fn translate_store_at(&mut self, array: Expr, index: Expr, value: Expr) -> Value {
+        let addr = self.translate_element_addr(array, index);
+        let unsigned = self.is_unsigned(&value);
+        let value = self.translate_expr(value);
+        let value = self.fit_int(value, self.int, unsigned);
+        self.builder.ins().store(MemFlags::new(), value, addr, 0);
+        value
+    }
+
+    /// `alloc(n)` calls the C library's `calloc` for `n` integers, which are
+    /// zeroed like an `array`'s, but outlive the function. The address is
+    /// null if there isn't enough memory.
+    fn translate_alloc(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 1 {
+            return self.fail(format!(
+                "`alloc` takes 1 argument but {} were given",
+                args.len()
+            ));
+        }
+        let len = self.translate_int_arg(args.into_iter().next().unwrap());
+        let size = self
+            .builder
+            .ins()
+            .iconst(self.int, i64::from(self.int.bytes()));
+        let results = self.translate_libc_call("calloc", &[len, size], true);
+        results[0]
+    }
+
+    /// `free(p)` calls the C library's `free`, so `p` must have come from
+    /// `alloc`, or be null.
+    fn translate_free(&mut self, args: Vec<Expr>) -> Value {
+        if args.len() != 1 {
+            return self.fail(format!(
+                "`free` takes 1 argument but {} were given",
+                args.len()
+            ));
+        }
+        let addr = self.translate_int_arg(args.into_iter().next().unwrap());
+        self.translate_libc_call("free", &[addr], false);
+        self.builder.ins().iconst(self.int, 0)
+    }
+
+    /// Translate `arg` as an integer of the toy language's integer type.
+    fn translate_int_arg(&mut self, arg: Expr) -> Value {
+        let unsigned = self.is_unsigned(&arg);
+        let value = self.translate_expr(arg);
+        self.fit_int(value, self.int, unsigned)
+    }
+
+    /// Call the C library function `name`, whose parameters and result, if
+    /// it `returns` one, are all pointer-sized integers. It's imported into
+    /// the module, which finds it in the host process when the function is
+    /// finalized.
+    fn translate_libc_call(&mut self, name: &str, args: &[Value], returns: bool) -> Vec<Value> {
+        let mut sig = self.module.make_signature();
+        for _arg in args {
+            sig.params.push(AbiParam::new(self.int));
+        }
+        if returns {
+            sig.returns.push(AbiParam::new(self.int));
+        }
         let callee = self
             .module
-            .declare_function(&name, Linkage::Import, &sig)
+            .declare_function(name, Linkage::Import, &sig)
             .expect("problem declaring function");
-        let local_callee = self
+        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let call = self.builder.ins().call(local_callee, args);
+        self.builder.inst_results(call).to_vec()
+    }
+
+    /// The remainder of dividing the float `lhs` by `rhs`, which has the sign
+    /// of `lhs`, as with integers. Cranelift has no instruction for it, so
+    /// it's computed by `float_rem`. The remainder of `f32`s is exactly that
+    /// of the same values as `f64`s.
+    fn translate_float_rem(&mut self, lhs: Value, rhs: Value) -> Value {
+        let ty = self.builder.func.dfg.value_type(lhs);
+        let (lhs, rhs) = if ty == types::F32 {
+            (
+                self.builder.ins().fpromote(types::F64, lhs),
+                self.builder.ins().fpromote(types::F64, rhs),
+            )
+        } else {
+            (lhs, rhs)
+        };
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(types::F64));
+        sig.params.push(AbiParam::new(types::F64));
+        sig.returns.push(AbiParam::new(types::F64));
+        let callee = self
             .module
-            .declare_func_in_func(callee, &mut self.builder.func);
+            .declare_function(FLOAT_REM, Linkage::Import, &sig)
+            .expect("problem declaring function");
+        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let call = self.builder.ins().call(local_callee, &[lhs, rhs]);
+        let result = self.builder.inst_results(call)[0];
+        if ty == types::F32 {
+            self.builder.ins().fdemote(ty, result)
+        } else {
+            result
+        }
+    }
+
+    /// Translate the address of the `index`th integer from the address
+    /// `base`.
+    fn translate_element_addr(&mut self, base: Expr, index: Expr) -> Value {
+        let unsigned = self.is_unsigned(&base);
+        let base = self.translate_expr(base);
+        let base = self.fit_int(base, self.int, unsigned);
+        let unsigned = self.is_unsigned(&index);
+        let index = self.translate_expr(index);
+        let index = self.fit_int(index, self.int, unsigned);
+        let offset = self
+            .builder
+            .ins()
+            .imul_imm(index, i64::from(self.int.bytes()));
+        self.builder.ins().iadd(base, offset)
+    }
+
+    fn translate_host_call(&mut self, name: &str, host: &HostFunction, args: Vec<Expr>) -> Value {
+        if args.len() != host.param_types.len() {
+            return self.fail(format!(
+                "`{}` takes {} but {} were given",
+                name,
+                arguments(host.param_types.len()),
+                args.len()
+            ));
+        }
+
+        let mut sig = self.module.make_signature();
+        for ty in &host.param_types {
+            sig.params.push(AbiParam::new(*ty));
+        }
+        sig.returns.push(AbiParam::new(host.ret_type));
+        let sig_ref = self.builder.import_signature(sig);
+
+        // Arguments are evaluated from left to right, as for any other call.
+        let mut arg_values = Vec::new();
+        for (arg, ty) in args.into_iter().zip(&host.param_types) {
+            let unsigned = self.is_unsigned(&arg);
+            let value = self.translate_expr(arg);
+            arg_values.push(self.convert_to(value, *ty, unsigned));
+        }
 
+        // Host functions aren't declared to the module at all; since we
+        // already know the address, just call it indirectly.
+        let callee = self.builder.ins().iconst(self.int, host.addr as i64);
+        let call = self
+            .builder
+            .ins()
+            .call_indirect(sig_ref, callee, &arg_values);
+        let result = self.builder.inst_results(call)[0];
+        self.convert_from(result, host.ret_type)
+    }
+
+    /// Convert a toy-language value into a value of type `ty`, treating it as
+    /// unsigned if it's an `unsigned` integer.
+    fn convert_to(&mut self, value: Value, ty: Type, unsigned: bool) -> Value {
+        let from = self.builder.func.dfg.value_type(value);
+        if from == ty {
+            value
+        } else if from.is_float() {
+            if !ty.is_float() {
+                self.builder.ins().fcvt_to_sint_sat(ty, value)
+            } else if ty.bits() > from.bits() {
+                self.builder.ins().fpromote(ty, value)
+            } else {
+                self.builder.ins().fdemote(ty, value)
+            }
+        } else if ty.is_float() && unsigned {
+            self.builder.ins().fcvt_from_uint(ty, value)
+        } else if ty.is_float() {
+            self.builder.ins().fcvt_from_sint(ty, value)
+        } else {
+            self.fit_int(value, ty, unsigned)
+        }
+    }
+
+    /// Convert a value of type `ty` into a toy-language value.
+    fn convert_from(&mut self, value: Value, ty: Type) -> Value {
+        if ty == self.int {
+            value
+        } else if ty.is_float() {
+            self.builder.ins().fcvt_to_sint_sat(self.int, value)
+        } else if ty.bits() < self.int.bits() {
+            self.builder.ins().sextend(self.int, value)
+        } else {
+            self.builder.ins().ireduce(self.int, value)
+        }
+    }
+
+    fn translate_inline_call(&mut self, callee: &Function, args: Vec<Expr>) -> Value {
+        // Evaluate the arguments in the caller, from left to right, just as
+        // for a call.
         let mut arg_values = Vec::new();
         for arg in args {
-            arg_values.push(self.translate_expr(arg))
+            let unsigned = self.is_unsigned(&arg);
+            arg_values.push((self.translate_expr(arg), unsigned))
         }
-        let call = self.builder.ins().call(local_callee, &arg_values);
-        self.builder.inst_results(call)[0]
+
+        // Give the callee its own set of variables. Parameters take the
+        // argument values, and everything else starts out as zero, just as it
+        // would on entry to the callee.
+        let types = analysis::variable_types(&callee.types, &callee.stmts);
+        let mut variables = HashMap::new();
+        for name in callee.params.iter().chain(Some(&callee.the_return)) {
+            declare_variable(
+                self.int,
+                &types,
+                &mut self.builder,
+                &mut variables,
+                &mut self.next_variable,
+                name,
+            );
+        }
+        for expr in &callee.stmts {
+            declare_variables_in_stmt(
+                self.int,
+                &types,
+                &mut self.builder,
+                &mut variables,
+                &mut self.next_variable,
+                expr,
+            );
+        }
+        for (name, var) in &variables {
+            let zero = zero(&mut self.builder, ir_type(self.int, &types, name));
+            self.builder.def_var(*var, zero);
+        }
+        for (name, (value, unsigned)) in callee.params.iter().zip(arg_values) {
+            let value = self.convert_to(value, ir_type(self.int, &types, name), unsigned);
+            self.builder.def_var(variables[name], value);
+        }
+
+        self.peak_variables = self.peak_variables.max(variables.len());
+
+        // Translate the callee's body in place of the call, and read its
+        // return variable for the value of the call.
+        // The caller's loops can't be left from inside the callee, and its
+        // labels can't be jumped to.
+        let caller_variables = mem::replace(&mut self.variables, variables);
+        let caller_types = mem::replace(&mut self.types, types);
+        let callee_slots = self.spill_variables(&analysis::address_taken(&callee.stmts));
+        let caller_slots = mem::replace(&mut self.slots, callee_slots);
+        let caller_loops = mem::take(&mut self.loops);
+        let callee_labels = self.create_label_blocks(&callee.stmts);
+        let caller_labels = mem::replace(&mut self.labels, callee_labels);
+        self.inlining.push(callee.name.clone());
+        for expr in callee.stmts.clone() {
+            self.translate_stmt(expr);
+        }
+        self.inlining.pop();
+        self.seal_label_blocks();
+        self.labels = caller_labels;
+        self.loops = caller_loops;
+        let return_value = self.use_variable(&callee.the_return);
+        self.variables = caller_variables;
+        self.slots = caller_slots;
+        self.types = caller_types;
+        let unsigned = analysis::return_type(callee).is_unsigned();
+        self.fit_int(return_value, self.int, unsigned)
     }
 
     fn translate_global_data_addr(&mut self, name: String) -> Value {
@@ -395,17 +2876,49 @@ impl<'a> FunctionTranslator<'a> {
             .module
             .declare_data(&name, Linkage::Export, true)
             .expect("problem declaring data object");
-        let local_id = self
-            .module
-            .declare_data_in_func(sym, &mut self.builder.func);
+        let local_id = self.module.declare_data_in_func(sym, self.builder.func);
 
         let pointer = self.module.target_config().pointer_type();
         self.builder.ins().symbol_value(pointer, local_id)
     }
+
+    /// A string literal is the address of a read-only data object holding
+    /// its bytes, which is defined along with the function.
+    fn translate_string(&mut self, contents: String) -> Value {
+        // Literals with the same contents share a data object, named after
+        // them. No name written in the toy language can start with `#`.
+        let sym = self
+            .module
+            .declare_data(&format!("#string {:?}", contents), Linkage::Local, false)
+            .expect("problem declaring data object");
+        let local_id = self.module.declare_data_in_func(sym, self.builder.func);
+        self.strings.push((sym, contents));
+
+        self.builder.ins().symbol_value(self.int, local_id)
+    }
+}
+
+/// The name compiled code calls `float_rem` by, which no function in the
+/// toy language can have.
+const FLOAT_REM: &str = "#float_rem";
+
+/// The remainder of `lhs` divided by `rhs`, for `%` on floats.
+extern "C" fn float_rem(lhs: f64, rhs: f64) -> f64 {
+    lhs % rhs
+}
+
+/// Create a description of the host machine, as `SimpleJITBuilder::new` does.
+fn native_isa() -> Box<dyn isa::TargetIsa> {
+    let flag_builder = settings::builder();
+    let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
+        panic!("host machine is not supported: {}", msg);
+    });
+    isa_builder.finish(settings::Flags::new(flag_builder))
 }
 
 fn declare_variables(
     int: types::Type,
+    types: &HashMap<String, ValueType>,
     builder: &mut FunctionBuilder,
     params: &[String],
     the_return: &str,
@@ -419,52 +2932,196 @@ fn declare_variables(
         // TODO: cranelift_frontend should really have an API to make it easy to set
         // up param variables.
         let val = builder.ebb_params(entry_ebb)[i];
-        let var = declare_variable(int, builder, &mut variables, &mut index, name);
+        let var = declare_variable(int, types, builder, &mut variables, &mut index, name);
         builder.def_var(var, val);
     }
-    let zero = builder.ins().iconst(int, 0);
-    let return_variable = declare_variable(int, builder, &mut variables, &mut index, the_return);
+    let zero = zero(builder, ir_type(int, types, the_return));
+    let return_variable =
+        declare_variable(int, types, builder, &mut variables, &mut index, the_return);
     builder.def_var(return_variable, zero);
     for expr in stmts {
-        declare_variables_in_stmt(int, builder, &mut variables, &mut index, expr);
+        declare_variables_in_stmt(int, types, builder, &mut variables, &mut index, expr);
     }
 
     variables
 }
 
-/// Recursively descend through the AST, translating all implicit
-/// variable declarations.
+/// The structs, enums and globals which the functions of the program `input`
+/// can use: those already `known`, and those the program defines, along with
+/// the globals it declares and their initial values. A struct or an enum may
+/// be defined again, as long as it has the same fields or variants, but a
+/// global may not, since it has a data object of its own.
+fn program_definitions(
+    input: &str,
+    cfg_flags: &HashSet<String>,
+    known: &Definitions,
+) -> Result<(Definitions, Vec<(String, i64)>), String> {
+    let found = parser::definitions(input, cfg_flags, known).map_err(|e| e.to_string())?;
+    let mut definitions = known.clone();
+    let mut globals = Vec::new();
+    let mut defined = HashSet::new();
+    for definition in found {
+        let (kind, part, table, name, names) = match definition {
+            Definition::Struct(name, fields) => {
+                ("struct", "field", &mut definitions.structs, name, fields)
+            }
+            Definition::Enum(name, variants) => {
+                ("enum", "variant", &mut definitions.enums, name, variants)
+            }
+            Definition::Global(name, value) => {
+                if !definitions.globals.insert(name.clone()) {
+                    return Err(format!("global `{}` is defined more than once", name));
+                }
+                globals.push((name, value));
+                continue;
+            }
+        };
+        if !defined.insert((kind, name.clone())) {
+            return Err(format!("{} `{}` is defined more than once", kind, name));
+        }
+        if let Some(repeated) = names
+            .iter()
+            .enumerate()
+            .find(|&(i, n)| names[..i].contains(n))
+            .map(|(_, n)| n)
+        {
+            return Err(format!(
+                "{} `{}` has more than one {} `{}`",
+                kind, name, part, repeated
+            ));
+        }
+        match table.get(&name) {
+            Some(existing) if *existing != names => {
+                return Err(format!(
+                    "{} `{}` is already defined with other {}s",
+                    kind, name, part
+                ));
+            }
+            _ => {}
+        }
+        table.insert(name, names);
+    }
+    Ok((definitions, globals))
+}
+
+/// Call `hook` at `point`, adding the EBBs it creates to `hook_ebbs`.
+fn call_hook(
+    hook: &Hook,
+    point: HookPoint,
+    builder: &mut FunctionBuilder,
+    hook_ebbs: &mut HashSet<Ebb>,
+) {
+    let first = builder.func.dfg.num_ebbs();
+    hook(point, builder);
+    hook_ebbs.extend((first..builder.func.dfg.num_ebbs()).map(Ebb::new));
+}
+
+/// The error for a function left out by its `#[cfg]` attribute.
+fn left_out(function: &Function) -> String {
+    format!("`{}` is left out by its `#[cfg]` attribute", function.name)
+}
+
+/// Something found wrong with a function by `compile_with_diagnostics`.
+struct Diagnostic {
+    /// Whether it's an error, rather than a warning.
+    error: bool,
+    /// Which check found it.
+    kind: &'static str,
+    message: String,
+    /// The line and column it starts at, if they're known.
+    span: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    fn error(kind: &'static str, message: String, span: Option<(usize, usize)>) -> Self {
+        Self {
+            error: true,
+            kind,
+            message,
+            span,
+        }
+    }
+
+    fn warning(kind: &'static str, message: String) -> Self {
+        Self {
+            error: false,
+            kind,
+            message,
+            span: None,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let span = match self.span {
+            Some((line, column)) => format!("{{\"line\": {}, \"column\": {}}}", line, column),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"severity\": \"{}\", \"kind\": \"{}\", \"message\": {}, \"span\": {}}}",
+            if self.error { "error" } else { "warning" },
+            self.kind,
+            json_string(&self.message),
+            span
+        )
+    }
+}
+
+/// Write `s` as a JSON string, quoted and escaped.
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Describe why code couldn't be generated for the function `name`, saying
+/// which kind of failure it was.
+fn codegen_error(name: &str, err: ModuleError) -> String {
+    let kind = match err {
+        ModuleError::Compilation(CodegenError::Verifier(errors)) => {
+            format!("its IR failed to verify:\n{}", errors)
+        }
+        ModuleError::Compilation(CodegenError::ImplLimitExceeded) => {
+            "it exceeds one of Cranelift's implementation limits".to_string()
+        }
+        ModuleError::Compilation(CodegenError::CodeTooLarge) => {
+            "its code is too large for the target".to_string()
+        }
+        err => err.to_string(),
+    };
+    format!("cannot generate code for `{}`: {}", name, kind)
+}
+
+/// Declare every variable which is assigned anywhere in `expr`, including
+/// within conditions and the arguments of calls, in the order they're
+/// assigned.
 fn declare_variables_in_stmt(
     int: types::Type,
+    types: &HashMap<String, ValueType>,
     builder: &mut FunctionBuilder,
     variables: &mut HashMap<String, Variable>,
     index: &mut usize,
     expr: &Expr,
 ) {
-    match *expr {
-        Expr::Assign(ref name, _) => {
-            declare_variable(int, builder, variables, index, name);
-        }
-        Expr::IfElse(ref _condition, ref then_body, ref else_body) => {
-            for stmt in then_body {
-                declare_variables_in_stmt(int, builder, variables, index, &stmt);
-            }
-            for stmt in else_body {
-                declare_variables_in_stmt(int, builder, variables, index, &stmt);
-            }
-        }
-        Expr::WhileLoop(ref _condition, ref loop_body) => {
-            for stmt in loop_body {
-                declare_variables_in_stmt(int, builder, variables, index, &stmt);
-            }
-        }
-        _ => (),
+    for name in analysis::assigned_variables(expr) {
+        declare_variable(int, types, builder, variables, index, name);
     }
 }
 
-/// Declare a single variable declaration.
+/// Declare a single variable declaration, of the type it has in `types`, or
+/// an integer if it isn't there.
 fn declare_variable(
     int: types::Type,
+    types: &HashMap<String, ValueType>,
     builder: &mut FunctionBuilder,
     variables: &mut HashMap<String, Variable>,
     index: &mut usize,
@@ -473,8 +3130,37 @@ fn declare_variable(
     let var = Variable::new(*index);
     if !variables.contains_key(name) {
         variables.insert(name.into(), var);
-        builder.declare_var(var, int);
+        builder.declare_var(var, ir_type(int, types, name));
         *index += 1;
     }
     var
 }
+
+/// The Cranelift type of values of type `ty`.
+fn cranelift_type(int: types::Type, ty: ValueType) -> types::Type {
+    match ty {
+        ValueType::Int | ValueType::Ptr | ValueType::Bool => int,
+        ValueType::I32 | ValueType::U32 => types::I32,
+        ValueType::U64 => types::I64,
+        ValueType::F32 => types::F32,
+        ValueType::F64 => types::F64,
+    }
+}
+
+/// The Cranelift type of the variable `name`, given the types of the
+/// variables which don't hold integers.
+fn ir_type(int: types::Type, types: &HashMap<String, ValueType>, name: &str) -> types::Type {
+    let ty = types.get(name).cloned().unwrap_or(ValueType::Int);
+    cranelift_type(int, ty)
+}
+
+/// A zero of type `ty`.
+fn zero(builder: &mut FunctionBuilder, ty: types::Type) -> Value {
+    if ty == types::F32 {
+        builder.ins().f32const(Ieee32::with_float(0.0))
+    } else if ty == types::F64 {
+        builder.ins().f64const(Ieee64::with_float(0.0))
+    } else {
+        builder.ins().iconst(ty, 0)
+    }
+}
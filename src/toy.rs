@@ -1,17 +1,84 @@
 extern crate cranelift;
 extern crate cranelift_module;
+extern crate cranelift_native;
 extern crate cranelift_simplejit;
 
+use cranelift::prelude::*;
+use std::env;
 use std::mem;
 use std::process;
+use std::slice;
+use std::sync::atomic::{AtomicIsize, Ordering};
 
+mod analysis;
+mod difftest;
 mod frontend;
+mod interp;
 mod jit;
 
 fn main() {
     // Create the JIT instance, which manages all generated functions and data.
     let mut jit = jit::JIT::new();
 
+    // Each demo can use the functions the ones before it compiled, so they
+    // run in order.
+    basics(&mut jit);
+    recursion(&mut jit);
+    iteration(&mut jit);
+    ternaries(&mut jit);
+    host_constants(&mut jit);
+    inlining(&mut jit);
+    pipes(&mut jit);
+    host_functions(&mut jit);
+    positions(&mut jit);
+    arguments(&mut jit);
+    operators(&mut jit);
+    records(&mut jit);
+    definitions(&mut jit);
+    lets(&mut jit);
+    else_ifs(&mut jit);
+    hooks(&mut jit);
+    codegen_errors(&mut jit);
+    programs(&mut jit);
+    conds(&mut jit);
+    builtins(&mut jit);
+    short_circuits(&mut jit);
+    remainders(&mut jit);
+    bitwise(&mut jit);
+    bools(&mut jit);
+    floats(&mut jit);
+    integer_types(&mut jit);
+    division(&mut jit);
+    memory(&mut jit);
+    addresses(&mut jit);
+    expressions(&mut jit);
+    code_bytes(&mut jit);
+    determinism();
+    deferred_finalization(&mut jit);
+    dynamic_calls(&mut jit);
+    introspection(&mut jit);
+    calling_convention(&mut jit);
+    interpreter(&mut jit);
+    diagnostics(&mut jit);
+    assertions(&mut jit);
+    negation(&mut jit);
+    overflow(&mut jit);
+    while_else(&mut jit);
+    tail_returns(&mut jit);
+    constant_folding(&mut jit);
+    loops(&mut jit);
+    gotos(&mut jit);
+    stats(&mut jit);
+    cfg_attributes(&mut jit);
+    source_limits(&mut jit);
+    ebb_limits(&mut jit);
+    strings(&mut jit);
+    hello(&mut jit);
+}
+
+// The demo deliberately uses `foo` as its first example's name.
+#[allow(clippy::disallowed_names)]
+fn basics(jit: &mut jit::JIT) {
     // A small test function.
     //
     // The `(c)` declares a return variable; the function returns whatever value
@@ -34,7 +101,7 @@ fn main() {
     ";
 
     // Pass the string to the JIT, and it returns a raw pointer to machine code.
-    let foo = jit.compile(&foo_code).unwrap_or_else(|msg| {
+    let foo = jit.compile(foo_code).unwrap_or_else(|msg| {
         eprintln!("error: {}", msg);
         process::exit(1);
     });
@@ -44,92 +111,2679 @@ fn main() {
     // is safe to be called.
     //
     // TODO: Is there a way to fold this transmute into `compile` above?
-    let foo = unsafe { mem::transmute::<_, fn(isize, isize) -> isize>(foo) };
+    let foo = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(foo) };
 
     // And now we can call it!
     println!("the answer is: {}", foo(1, 0));
+    assert_eq!(foo(1, 0), 42);
+    assert_eq!((foo(1, 1), foo(0, 1)), (32, 52));
+}
 
-    // -------------------------------------------------------------------------//
-
-    // Another example: Recursive fibonacci.
-    let recursive_fib_code = "\
-        fn recursive_fib(n) -> (r) {
-            r = if n == 0 {
-                     0
+// Another example: Recursive fibonacci. Later demos interpret it, and compile
+// it again.
+const RECURSIVE_FIB_CODE: &str = "\
+    fn recursive_fib(n) -> (r) {
+        r = if n == 0 {
+                 0
+            } else {
+                if n == 1 {
+                    1
                 } else {
-                    if n == 1 {
-                        1
-                    } else {
-                        recursive_fib(n - 1) + recursive_fib(n - 2)
-                    }
+                    recursive_fib(n - 1) + recursive_fib(n - 2)
                 }
-        }
-    ";
+            }
+    }
+";
 
-    // Same as above.
-    let recursive_fib = jit.compile(&recursive_fib_code).unwrap_or_else(|msg| {
+fn recursion(jit: &mut jit::JIT) {
+    // Pass the string to the JIT, as `basics` does.
+    let recursive_fib = jit.compile(RECURSIVE_FIB_CODE).unwrap_or_else(|msg| {
         eprintln!("error: {}", msg);
         process::exit(1);
     });
-    let recursive_fib = unsafe { mem::transmute::<_, fn(isize) -> isize>(recursive_fib) };
+    let recursive_fib = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(recursive_fib) };
 
     // And we can now call it!
     println!("recursive_fib(10) = {}", recursive_fib(10));
+    assert_eq!(recursive_fib(10), 55);
+}
 
-    // -------------------------------------------------------------------------//
+// Another example: Iterative fibonacci. Code can be annotated with `//`
+// and `/* */` comments. Later demos interpret it, and compile it again.
+const ITERATIVE_FIB_CODE: &str = "\
+    // The `n`th Fibonacci number, counting from fib(0) = 0.
+    fn iterative_fib(n) -> (r) {
+        if n == 0 {
+            r = 0
+        } else {
+            n = n - 1
+            a = 0 // the number before `r`
+            r = 1 /* fib(1) */
 
-    // Another example: Iterative fibonacci.
-    let iterative_fib_code = "\
-        fn iterative_fib(n) -> (r) {
-            if n == 0 {
-                r = 0
-            } else {
+            // Step forward `n` times.
+            while n != 0 {
+                t = r
+                r = r + a
+                a = t
                 n = n - 1
-                a = 0
-                r = 1
-                while n != 0 {
-                    t = r
-                    r = r + a
-                    a = t
-                    n = n - 1
-                }
             }
         }
+    }
+";
+
+fn iteration(jit: &mut jit::JIT) {
+    // Pass the string to the JIT, as `basics` does.
+    let iterative_fib = jit.compile(ITERATIVE_FIB_CODE).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let iterative_fib = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(iterative_fib) };
+
+    // And we can now call it!
+    println!("iterative_fib(10) = {}", iterative_fib(10));
+    assert_eq!(iterative_fib(10), 55);
+    assert_eq!(iterative_fib(0), 0);
+}
+
+// Another example: A chain of ternary conditionals. The chain groups to
+// the right, so each `:` introduces the next test. A later demo interprets
+// it.
+const CLASSIFY_CODE: &str = "\
+    fn classify(n) -> (r) {
+        r = n < 10 ? 1 : n < 20 ? 2 : 3
+    }
+";
+
+fn ternaries(jit: &mut jit::JIT) {
+    // Pass the string to the JIT, as `basics` does.
+    let classify = jit.compile(CLASSIFY_CODE).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let classify = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(classify) };
+
+    // And we can now call it!
+    for n in &[5, 15, 25] {
+        println!("classify({}) = {}", n, classify(*n));
+    }
+    assert_eq!((classify(5), classify(15), classify(25)), (1, 2, 3));
+}
+
+fn host_constants(jit: &mut jit::JIT) {
+    // Another example: Constants provided by the host. `VERSION` isn't a
+    // variable in the function; its value comes from `define_constant`.
+    jit.define_constant("VERSION", 3);
+    let version_code = "\
+        fn version() -> (r) {
+            r = VERSION * 10
+        }
     ";
 
     // Same as above.
-    let iterative_fib = jit.compile(&iterative_fib_code).unwrap_or_else(|msg| {
+    let version = jit.compile(version_code).unwrap_or_else(|msg| {
         eprintln!("error: {}", msg);
         process::exit(1);
     });
-    let iterative_fib = unsafe { mem::transmute::<_, fn(isize) -> isize>(iterative_fib) };
+    let version = unsafe { mem::transmute::<*const u8, fn() -> isize>(version) };
 
     // And we can now call it!
-    println!("iterative_fib(10) = {}", iterative_fib(10));
+    println!("version() = {}", version());
+    assert_eq!(version(), 30);
 
-    // -------------------------------------------------------------------------//
+    // Integer literals can also be written in hexadecimal, octal, or binary.
+    let radixes_code = "\
+        fn radixes() -> (r) {
+            a = 0o17 == 15
+            b = 0b101 == 5
+            c = 0x1F == 31
+            r = a + b + c
+        }
+    ";
 
-    // Let's say hello, by calling into libc. The puts function is resolved by
-    // dlsym to the libc function, and the string &hello_string is defined below.
-    let hello_code = "\
-        fn hello() -> (r) {
-            puts(&hello_string)
+    // Same as above.
+    let radixes = jit.compile(radixes_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let radixes = unsafe { mem::transmute::<*const u8, fn() -> isize>(radixes) };
+
+    // And we can now call it!
+    println!("radixes() = {}", radixes());
+    assert_eq!(radixes(), 3);
+
+    // The bounds of the integer type are predefined constants too. Arithmetic
+    // wraps around, so going past one bound leads to the other.
+    let bounds_code = "\
+        fn bounds() -> (r) {
+            r = INT_MAX + 1 == INT_MIN
         }
     ";
 
-    jit.create_data("hello_string", "hello world!\0".as_bytes().to_vec())
-        .unwrap_or_else(|msg| {
+    // Same as above.
+    let bounds = jit.compile(bounds_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let bounds = unsafe { mem::transmute::<*const u8, fn() -> isize>(bounds) };
+
+    // And we can now call it!
+    println!("bounds() = {}", bounds());
+    assert_eq!(bounds(), 1);
+}
+
+fn inlining(jit: &mut jit::JIT) {
+    // Functions can ask to be inlined into their callers with `#[inline]`, or
+    // never to be with `#[noinline]`.
+    let double_code = "\
+        #[inline]
+        fn double(x) -> (r) {
+            r = x * 2
+        }
+    ";
+    let triple_code = "\
+        #[noinline]
+        fn triple(x) -> (r) {
+            r = x * 3
+        }
+    ";
+    let six_times_code = "\
+        fn six_times(x) -> (r) {
+            r = double(triple(x))
+        }
+    ";
+    for code in &[double_code, triple_code] {
+        jit.compile(code).unwrap_or_else(|msg| {
             eprintln!("error: {}", msg);
             process::exit(1);
         });
+    }
+
+    // The IR for `six_times` contains a call to `triple`, but `double`'s
+    // multiply appears directly in its body.
+    let ir = jit.ir(six_times_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("{}", ir);
+    assert_eq!(ir.matches(" call ").count(), 1);
+    assert_eq!(ir.matches(" imul ").count(), 1);
+
+    // Same as above.
+    let six_times = jit.compile(six_times_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let six_times = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(six_times) };
+
+    // And we can now call it!
+    println!("six_times(7) = {}", six_times(7));
+    assert_eq!(six_times(7), 42);
+}
+
+fn pipes(jit: &mut jit::JIT) {
+    // Another example: The pipe operator. `x |> double |> inc` means the same
+    // thing as `inc(double(x))`.
+    let inc_code = "\
+        fn inc(x) -> (r) {
+            r = x + 1
+        }
+    ";
+    let piped_code = "\
+        fn piped(x) -> (r) {
+            r = x |> double |> inc
+        }
+    ";
+    jit.compile(inc_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+
+    // Same as above.
+    let piped = jit.compile(piped_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let piped = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(piped) };
+
+    // And we can now call it!
+    println!("piped(5) = {}", piped(5));
+    assert_eq!(piped(5), 11);
+}
+
+fn host_functions(jit: &mut jit::JIT) {
+    // Host functions can be registered with their real signatures, and the
+    // JIT converts to and from the toy language's integers around the call.
+    jit.register_fn(
+        "scale",
+        &[types::I32, types::F64],
+        types::F64,
+        scale as *const u8,
+    )
+    .unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let scaled_code = "\
+        fn scaled(x) -> (r) {
+            r = scale(x, 3)
+        }
+    ";
+
+    // Same as above.
+    let scaled = jit.compile(scaled_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let scaled = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(scaled) };
+
+    // And we can now call it!
+    println!("scaled(7) = {}", scaled(7));
+    assert_eq!(scaled(7), 21);
+
+    // They're called with as many arguments as they take, like any other
+    // function.
+    let underscaled = jit.compile("fn underscaled(x) -> (r) {\n r = scale(x)\n}\n");
+    match underscaled {
+        Ok(_) => println!("underscaled compiled"),
+        Err(ref msg) => println!("underscaled failed: {}", msg),
+    }
+    assert_eq!(
+        underscaled,
+        Err("`scale` takes 2 arguments but 1 were given".to_string())
+    );
+}
+
+fn positions(jit: &mut jit::JIT) {
+    // `@line` and `@column` are replaced by the position they appear at in the
+    // source, which is handy for logging.
+    let position_code = "\
+        fn position() -> (r) {
+            x = 1
+            r = @line * 100 + @column
+        }
+    ";
+
+    // Same as above.
+    let position = jit.compile(position_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let position = unsafe { mem::transmute::<*const u8, fn() -> isize>(position) };
+
+    // And we can now call it!
+    let r = position();
+    println!("position() = line {}, column {}", r / 100, r % 100);
+    assert_eq!((r / 100, r % 100), (3, 31));
+}
+
+fn arguments(jit: &mut jit::JIT) {
+    // Call arguments are evaluated from left to right, which matters when they
+    // have side effects. Here the first argument sees `x` before the second
+    // argument multiplies it.
+    let arg_order_code = "\
+        fn arg_order(x) -> (r) {
+            r = sub_args(x = x + 1, x = x * 3)
+        }
+    ";
+    let sub_args_code = "\
+        fn sub_args(a, b) -> (r) {
+            r = b - a
+        }
+    ";
+
+    // Same as above.
+    jit.compile(sub_args_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let arg_order = jit.compile(arg_order_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let arg_order = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(arg_order) };
+
+    // Evaluated right to left, this would be 4 - 3 = 1 instead.
+    println!("arg_order(1) = {}", arg_order(1));
+    assert_eq!(arg_order(1), 6 - 2);
+
+    // When all the arguments are written `name = value`, and some of the
+    // names are the callee's parameters, they're passed by name instead, in
+    // whichever order they're written.
+    let by_name_code = "\
+        fn by_name(x) -> (r) {
+            r = sub_args(b = x * 10, a = x)
+        }
+    ";
+    let by_name = jit.compile(by_name_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let by_name = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(by_name) };
+    println!("by_name(2) = {}", by_name(2));
+    assert_eq!(by_name(2), 20 - 2);
+
+    // Otherwise, an argument written `name = value` assigns to a variable of
+    // the caller's, like an assignment anywhere else.
+    let assigning_args_code = "\
+        fn assigning_args() -> (r) {
+            r = sub_args(q = 1, 2) * 10 + q
+        }
+    ";
+    let assigning_args = jit.compile(assigning_args_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let assigning_args = unsafe { mem::transmute::<*const u8, fn() -> isize>(assigning_args) };
+    assert_eq!(assigning_args(), 11);
+    assert_eq!(jit.interpret(assigning_args_code, &[]), Ok(11));
+
+    // Trailing parameters can have constant default values, which are used
+    // when a call leaves them out.
+    let defaults_code = "\
+        fn offset(a, b = 10) -> (r) {
+            r = a + b
+        }
+
+        fn use_offset(x) -> (r) {
+            r = offset(x) * 100 + offset(x, 1) + offset(a = x) * 10000
+        }
+    ";
+    let program = jit.compile_program(defaults_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let use_offset =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["use_offset"]) };
+    println!("use_offset(5) = {}", use_offset(5));
+    assert_eq!(use_offset(5), 15 * 100 + 6 + 15 * 10000);
+    let varying = jit.compile("fn varying(a, b = a) -> (r) {\n r = b\n}\n");
+    match varying {
+        Ok(_) => println!("varying compiled"),
+        Err(ref msg) => println!("varying failed: {}", msg),
+    }
+    assert!(varying.is_err());
+
+    // Lists of parameters and arguments may end with a comma, unless they're
+    // otherwise empty.
+    let trailing_code = "\
+        fn trailing(a, b,) -> (r) {
+            r = a * 10 + b
+        }
+
+        fn call_trailing() -> (r) {
+            r = trailing(1, 2,)
+        }
+    ";
+    let program = jit.compile_program(trailing_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let call_trailing =
+        unsafe { mem::transmute::<*const u8, fn() -> isize>(program["call_trailing"]) };
+    println!("call_trailing() = {}", call_trailing());
+    assert_eq!(call_trailing(), 12);
+    let lone_comma = jit.compile("fn lone_comma(,) -> (r) {\n}\n");
+    match lone_comma {
+        Ok(_) => println!("lone_comma compiled"),
+        Err(ref msg) => println!("lone_comma failed: {}", msg),
+    }
+    assert!(lone_comma.is_err());
+
+    // Names which aren't parameters, or which are given twice, are errors,
+    // whether the call is interpreted or compiled.
+    for code in &[
+        "fn misnamed() -> (r) {\n r = sub_args(a = 1, c = 2)\n}\n",
+        "fn renamed() -> (r) {\n r = sub_args(a = 1, a = 2)\n}\n",
+    ] {
+        let interpreted = jit.interpret(code, &[]);
+        match interpreted {
+            Ok(result) => println!("interpreted {}", result),
+            Err(ref msg) => println!("interpreting failed: {}", msg),
+        }
+        let compiled = jit.compile(code);
+        match compiled {
+            Ok(_) => println!("compiled"),
+            Err(ref msg) => println!("compiling failed: {}", msg),
+        }
+        assert!(interpreted.is_err());
+        assert!(compiled.is_err());
+    }
+}
+
+fn operators(jit: &mut jit::JIT) {
+    // Programs can define their own infix operators, which call the function
+    // defined with them. They bind less tightly than any built-in operator,
+    // so here `x <+> INT_MAX - 1` is `x <+> (INT_MAX - 1)`.
+    let operator_code = "\
+        operator <+> (a, b) -> (r) {
+            r = a + b
+            if a > 0 {
+                if r < b {
+                    r = INT_MAX
+                } else {
+                }
+            } else {
+            }
+        }
+
+        fn nearly_max(x) -> (r) {
+            r = x <+> INT_MAX - 1
+        }
+    ";
+    let program = jit.compile_program(operator_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let nearly_max =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["nearly_max"]) };
+    println!(
+        "nearly_max(-1) = {}, nearly_max(1) = {}, nearly_max(5) = {}",
+        nearly_max(-1),
+        nearly_max(1),
+        nearly_max(5)
+    );
+    assert_eq!(nearly_max(-1), isize::MAX - 2);
+    assert_eq!(nearly_max(1), isize::MAX);
+    assert_eq!(nearly_max(5), isize::MAX);
+}
+
+fn records(jit: &mut jit::JIT) {
+    // Records are passed by value by flattening them: `p: { x, y }` is two
+    // parameters, `p.x` and `p.y`, and `{ x: .., y: .. }` is two arguments.
+    let sum_point_code = "\
+        fn sum_point(p: { x, y }) -> (r) {
+            r = p.x + p.y
+        }
+    ";
+    let use_point_code = "\
+        fn use_point(a) -> (r) {
+            r = sum_point({ x: a, y: a * 2 })
+        }
+    ";
 
     // Same as above.
-    let hello = jit.compile(&hello_code).unwrap_or_else(|msg| {
+    jit.compile(sum_point_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let use_point = jit.compile(use_point_code).unwrap_or_else(|msg| {
         eprintln!("error: {}", msg);
         process::exit(1);
     });
-    let hello = unsafe { mem::transmute::<_, fn() -> isize>(hello) };
+    let use_point = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(use_point) };
 
     // And we can now call it!
-    hello();
+    println!("use_point(5) = {}", use_point(5));
+
+    // A struct names the fields of a record, so that `p: Point` is the same
+    // as `p: { x, y }`. Assigning `Point { .. }` to `p` assigns `p.x` and
+    // `p.y`, with any fields left out being zero, and passing one passes its
+    // fields in the order the struct defines them. A program's structs can
+    // be used anywhere in it.
+    let struct_code = "\
+        fn use_dot(n) -> (r) {
+            p = Point { y: n + 1 }
+            p.x = n
+            r = dot(Point { x: p.x, y: p.y }, Point { y: 1, x: 2 })
+        }
+
+        fn dot(a: Point, b: Point) -> (r) {
+            r = a.x * b.x + a.y * b.y
+        }
+
+        struct Point { x, y }
+    ";
+    let program = jit.compile_program(struct_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let use_dot = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["use_dot"]) };
+    println!("use_dot(5) = {}", use_dot(5));
+    assert_eq!(use_dot(5), 16);
+
+    // `(a, b) = (x, y)` evaluates every value on the right before assigning
+    // any of them, so it can compute several values at once. This steps
+    // through the Fibonacci numbers two at a time.
+    let fib_pairs_code = "
+        fn fib_pairs(n) -> (r) {
+            (a, b) = (0, 1)
+            repeat n {
+                (a, b) = (b, a + b)
+            }
+            r = a
+        }
+    ";
+    let fib_pairs = jit.compile(fib_pairs_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let fib_pairs = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(fib_pairs) };
+    println!("fib_pairs(10) = {}", fib_pairs(10));
+    assert_eq!(fib_pairs(10), 55);
+}
+
+fn definitions(jit: &mut jit::JIT) {
+    // An enum names constants, counting up from zero: `Color::Blue` is 2.
+    let enum_code = "\
+        enum Color { Red, Green, Blue }
+
+        fn next_color(c) -> (r) {
+            r = if c == Color::Blue {
+                Color::Red
+            } else {
+                c + 1
+            }
+        }
+    ";
+    let program = jit.compile_program(enum_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let next_color =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["next_color"]) };
+    println!("next_color(2) = {}", next_color(2));
+    assert_eq!(next_color(0), 1);
+    assert_eq!(next_color(2), 0);
+
+    // A global is shared by every function, and keeps its value between
+    // calls.
+    let global_code = "\
+        global counter = 0
+
+        fn bump(n) -> (r) {
+            counter = counter + n
+            r = counter
+        }
+
+        fn bumped() -> (r) {
+            r = counter
+        }
+    ";
+    let program = jit.compile_program(global_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let bump = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["bump"]) };
+    let bumped = unsafe { mem::transmute::<*const u8, fn() -> isize>(program["bumped"]) };
+    assert_eq!(bump(2), 2);
+    assert_eq!(bump(3), 5);
+    println!("bumped() = {}", bumped());
+    assert_eq!(bumped(), 5);
+
+    // Constants are worked out when the program is compiled, and may use the
+    // ones defined before them.
+    let const_code = "\
+        const WIDTH = 640
+        const HEIGHT = WIDTH * 3 >> 2
+
+        fn pixels() -> (r) {
+            r = WIDTH * HEIGHT
+        }
+    ";
+    let program = jit.compile_program(const_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let pixels = unsafe { mem::transmute::<*const u8, fn() -> isize>(program["pixels"]) };
+    println!("pixels() = {}", pixels());
+    assert_eq!(pixels(), 307200);
+}
+
+fn lets(jit: &mut jit::JIT) {
+    // A variable bound with `let` lasts until the end of its block, so other
+    // blocks, and the rest of the function, can use the same name for
+    // something else.
+    let let_code = "\
+        fn scoped(n) -> (r) {
+            x = 5
+            i = 0
+            while i < n {
+                let x = i * i
+                r = r + x
+                i = i + 1
+            }
+            if r > 1 {
+                let x = 100
+                r = r + x
+            } else {
+                r = r - 1
+            }
+            r = r + x
+        }
+    ";
+    let scoped = jit.compile(let_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let scoped = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(scoped) };
+    println!("scoped(3) = {}", scoped(3));
+    assert_eq!(scoped(3), 110);
+    assert_eq!(scoped(0), 4);
+
+    // An inner `let` shadows an outer one with a variable of its own, and so
+    // does another `let` in the same block, whose value can use the one
+    // before it.
+    let shadow_code = "\
+        fn shadowed(n) -> (r) {
+            let x = n
+            if n > 0 {
+                let x = x * 10
+                let x = x + 1
+                r = x
+            } else {
+                r = 0
+            }
+            r = r + x
+        }
+    ";
+    let shadowed = jit.compile(shadow_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let shadowed = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(shadowed) };
+    println!("shadowed(4) = {}", shadowed(4));
+    assert_eq!(shadowed(4), 45);
+    assert_eq!(shadowed(0), 0);
+}
+
+fn else_ifs(jit: &mut jit::JIT) {
+    // A chain of `else if`s tests each condition in turn.
+    let sign_code = "\
+        fn sign(x) -> (r) {
+            r = if x < 0 {
+                -1
+            } else if x == 0 {
+                0
+            } else {
+                1
+            }
+        }
+    ";
+    let sign = jit.compile(sign_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let sign = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(sign) };
+    println!("sign(-7) = {}", sign(-7));
+    assert_eq!((sign(-7), sign(0), sign(7)), (-1, 0, 1));
+}
+
+fn hooks(jit: &mut jit::JIT) {
+    // A hook can add instructions to the entry and exit of every function
+    // compiled while it's set. This one counts calls in `CALLS`.
+    jit.set_hook(|point, builder| {
+        if point == jit::HookPoint::Entry {
+            let int = types::I64;
+            let addr = builder
+                .ins()
+                .iconst(int, &CALLS as *const AtomicIsize as i64);
+            let calls = builder.ins().load(int, MemFlags::new(), addr, 0);
+            let calls = builder.ins().iadd_imm(calls, 1);
+            builder.ins().store(MemFlags::new(), calls, addr, 0);
+        }
+    });
+    let counted_code = "\
+        fn counted(x) -> (r) {
+            r = x + 1
+        }
+    ";
+
+    // Same as above.
+    let counted = jit.compile(counted_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    jit.clear_hook();
+    let counted = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(counted) };
+
+    // And we can now call it!
+    for x in 0..3 {
+        counted(x);
+    }
+    println!("counted was called {} times", CALLS.load(Ordering::SeqCst));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+}
+
+fn codegen_errors(jit: &mut jit::JIT) {
+    // When code can't be generated for a function, the error says which
+    // function it was, and why. This hook adds two integers of different
+    // widths, which the verifier rejects.
+    jit.set_hook(|point, builder| {
+        if point == jit::HookPoint::Entry {
+            let wide = builder.ins().iconst(types::I64, 1);
+            let narrow = builder.ins().iconst(types::I32, 1);
+            builder.ins().iadd(wide, narrow);
+        }
+    });
+    let mismatched_code = "\
+        fn mismatched() -> (r) {
+        }
+    ";
+    let mismatched = jit.compile(mismatched_code);
+    jit.clear_hook();
+    match mismatched {
+        Ok(_) => println!("mismatched compiled"),
+        Err(ref msg) => println!("mismatched failed: {}", msg),
+    }
+    assert!(mismatched.unwrap_err().contains("`mismatched`"));
+
+    // The translator's sealing of EBBs can be checked, turning a mistake into
+    // an error rather than wrong SSA values. Only its own EBBs are checked,
+    // so a hook which adds an EBB of its own seals it itself.
+    jit.set_check_sealing(true);
+    jit.set_hook(|point, builder| {
+        if point == jit::HookPoint::Entry {
+            let ebb = builder.create_ebb();
+            builder.ins().jump(ebb, &[]);
+            builder.switch_to_block(ebb);
+            builder.seal_block(ebb);
+        }
+    });
+    let sealed_code = "\
+        fn sealed(x) -> (r) {
+            while x > 0 {
+                r = r + x
+                x = x - 1
+            }
+        }
+    ";
+    let sealed = jit.compile(sealed_code);
+    jit.clear_hook();
+    jit.set_check_sealing(false);
+    let sealed = sealed.unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let sealed = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(sealed) };
+    assert_eq!(sealed(4), 10);
+}
+
+fn programs(jit: &mut jit::JIT) {
+    // Several functions can be compiled together as a program, as long as
+    // their names are all different.
+    let program_code = "\
+        fn half(x) -> (r) {
+            r = x / 2
+        }
+
+        fn quarter(x) -> (r) {
+            r = half(half(x))
+        }
+    ";
+    let program = jit.compile_program(program_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let quarter = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["quarter"]) };
+    println!("quarter(20) = {}", quarter(20));
+
+    // Since the whole program is declared before any of it is defined,
+    // functions can call functions which are defined after them.
+    let forward_code = "\
+        fn is_even(n) -> (r) {
+            r = n == 0 ? 1 : is_odd(n - 1)
+        }
+
+        fn is_odd(n) -> (r) {
+            r = n == 0 ? 0 : is_even(n - 1)
+        }
+    ";
+    let program = jit.compile_program(forward_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let is_even = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["is_even"]) };
+    let is_odd = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["is_odd"]) };
+    println!("is_even(10) = {}, is_odd(7) = {}", is_even(10), is_odd(7));
+    assert_eq!((is_even(10), is_even(7), is_odd(7)), (1, 0, 1));
+
+    // An alias gives a function a second name, sharing the same code.
+    let alias_code = "\
+        fn original(x) -> (r) {
+            r = x * 3 + 1
+        }
+
+        alias copy = original
+
+        fn difference(x) -> (r) {
+            r = copy(x) - original(x)
+        }
+    ";
+    let program = jit.compile_program(alias_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let original = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["original"]) };
+    let copy = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["copy"]) };
+    let difference =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["difference"]) };
+    println!(
+        "original(4) = {}, copy(4) = {}, difference(4) = {}",
+        original(4),
+        copy(4),
+        difference(4)
+    );
+    assert_eq!(original(4), copy(4));
+    assert_eq!(program["original"], program["copy"]);
+
+    let duplicate_code = "\
+        fn f() -> (r) {
+            r = 1
+        }
+
+        fn f() -> (r) {
+            r = 2
+        }
+    ";
+    let duplicate = jit.compile_program(duplicate_code);
+    match duplicate {
+        Ok(_) => println!("duplicate program compiled"),
+        Err(ref msg) => println!("duplicate program failed: {}", msg),
+    }
+    assert!(duplicate.is_err());
+
+    // A program which fails to compile leaves nothing half-compiled behind,
+    // so the JIT can go on compiling afterwards, even the same functions.
+    let failing_code = "\
+        fn ok1() -> (r) {
+            r = 1
+        }
+
+        fn bad(x) -> (r) {
+            p = &x
+            x = 1.5
+            r = *p
+        }
+    ";
+    let msg = jit.compile_program(failing_code).err().unwrap();
+    println!("failing program: {}", msg);
+    let after_code = "\
+        fn after() -> (r) {
+            r = 2
+        }
+    ";
+    let after = jit.compile(after_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let after = unsafe { mem::transmute::<*const u8, fn() -> isize>(after) };
+    assert_eq!(after(), 2);
+    let retry_code = "\
+        fn ok1() -> (r) {
+            r = 1
+        }
+    ";
+    let program = jit.compile_program(retry_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let ok1 = unsafe { mem::transmute::<*const u8, fn() -> isize>(program["ok1"]) };
+    assert_eq!(ok1(), 1);
+
+    // That's so even when the mistake only shows up in the IR it translates
+    // to, after every function has been translated.
+    let unverified_code = "\
+        fn good() -> (r) {
+            r = 3
+        }
+
+        fn one_half() -> (r: f64) {
+            r = 0.5
+        }
+
+        fn unverified() -> (r) {
+            r = one_half() + 1
+        }
+    ";
+    let msg = jit.compile_program(unverified_code).err().unwrap();
+    println!("unverified program: {}", msg);
+    let good_code = "\
+        fn good() -> (r) {
+            r = 3
+        }
+    ";
+    let good = jit.compile(good_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let good = unsafe { mem::transmute::<*const u8, fn() -> isize>(good) };
+    assert_eq!(good(), 3);
+
+    // A program can also just be parsed, to find out what it defines.
+    let index_code = "\
+        fn zero() -> (r) {
+        }
+
+        fn one(a) -> (r) {
+            r = a
+        }
+
+        alias uno = one
+
+        fn two(a, b) -> (sum) {
+            sum = a + b
+        }
+    ";
+    let index = jit::JIT::parse_program(index_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    for (name, params, the_return) in &index {
+        println!("{}({}) -> ({})", name, params.join(", "), the_return);
+    }
+    let names: Vec<_> = index.iter().map(|f| (f.0.as_str(), f.1.len())).collect();
+    assert_eq!(names, [("zero", 0), ("one", 1), ("two", 2)]);
+}
+
+fn conds(jit: &mut jit::JIT) {
+    // A `cond` picks the first arm whose guard holds.
+    let magnitude_code = "\
+        fn magnitude(n) -> (r) {
+            r = cond {
+                n < 0 => 0 - 1,
+                n < 10 => 10,
+                n < 100 => 100,
+                _ => 1000,
+            }
+        }
+    ";
+    let magnitude = jit.compile(magnitude_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let magnitude = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(magnitude) };
+    println!("magnitude(5) = {}", magnitude(5));
+    assert_eq!(magnitude(5), 10);
+    assert_eq!(
+        [magnitude(-5), magnitude(50), magnitude(500)],
+        [-1, 100, 1000]
+    );
+}
+
+fn builtins(jit: &mut jit::JIT) {
+    // `isqrt` is a builtin which computes integer square roots with a loop
+    // generated in place.
+    let root_code = "\
+        fn root(x) -> (r) {
+            r = isqrt(x)
+        }
+    ";
+
+    // Same as above.
+    let root = jit.compile(root_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let root = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(root) };
+
+    // And we can now call it!
+    for &(x, expected) in &[(16, 4), (15, 3), (0, 0)] {
+        println!("root({}) = {}", x, root(x));
+        assert_eq!(root(x), expected);
+    }
+
+    // Builtins are called with as many arguments as they take, in programs
+    // too.
+    let two_roots_code = "fn two_roots() -> (r) {\n r = isqrt(1, 2)\n}\n";
+    let two_roots = jit.compile(two_roots_code);
+    match two_roots {
+        Ok(_) => println!("two_roots compiled"),
+        Err(ref msg) => println!("two_roots failed: {}", msg),
+    }
+    assert_eq!(
+        two_roots,
+        Err("`isqrt` takes 1 argument but 2 were given".to_string())
+    );
+    assert!(jit.compile_program(two_roots_code).is_err());
+
+    // The builtins can be listed. Any other name, like `min`, is free to be
+    // used for a function of our own.
+    let builtins = jit::JIT::builtins();
+    println!("builtins: {}", builtins.join(", "));
+    assert!(builtins.contains(&"assert_eq".to_string()));
+    assert!(builtins.contains(&"isqrt".to_string()));
+    assert!(!builtins.contains(&"min".to_string()));
+    let min_code = "\
+        fn min(a, b) -> (r) {
+            r = a < b ? a : b
+        }
+
+        fn smaller_root(a, b) -> (r) {
+            r = min(isqrt(a), isqrt(b))
+        }
+    ";
+    let program = jit.compile_program(min_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let smaller_root =
+        unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(program["smaller_root"]) };
+    println!("smaller_root(50, 10) = {}", smaller_root(50, 10));
+    assert_eq!(smaller_root(50, 10), 3);
+}
+
+fn short_circuits(jit: &mut jit::JIT) {
+    // `&&` and `||` only evaluate their right operand when they need to. To
+    // check, a callback can be called whenever a right operand is evaluated.
+    jit.set_rhs_callback(Some(count_rhs));
+    let both_code = "\
+        fn both(a, b) -> (r) {
+            r = a && b
+        }
+    ";
+
+    // Same as above.
+    let both = jit.compile(both_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    jit.set_rhs_callback(None);
+    let both = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(both) };
+
+    // And we can now call it!
+    for &(a, b, evaluated) in &[(1, 7, 1), (0, 7, 0)] {
+        let before = RHS_EVALUATIONS.load(Ordering::SeqCst);
+        let r = both(a, b);
+        let after = RHS_EVALUATIONS.load(Ordering::SeqCst);
+        println!(
+            "both({}, {}) = {}, evaluating the right operand {} times",
+            a,
+            b,
+            r,
+            after - before
+        );
+        assert_eq!(after - before, evaluated);
+    }
+}
+
+fn remainders(jit: &mut jit::JIT) {
+    // `%` is the remainder of a division, so it can find multiples. This sums
+    // the multiples of 3 or 5 below `n`.
+    let multiples_code = "\
+        fn multiples(n) -> (r) {
+            i = 1
+            while i < n {
+                if i % 3 == 0 || i % 5 == 0 {
+                    r = r + i
+                } else {
+                }
+                i = i + 1
+            }
+        }
+    ";
+    let multiples = jit.compile(multiples_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let multiples = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(multiples) };
+    println!("multiples(1000) = {}", multiples(1000));
+    assert_eq!(multiples(10), 23);
+    assert_eq!(multiples(1000), 233168);
+}
+
+fn bitwise(jit: &mut jit::JIT) {
+    // `&`, `|` and `^` work on the bits of their operands. This counts the
+    // bits which are set, clearing the lowest one each time around.
+    let popcount_code = "
+        fn popcount(x) -> (r) {
+            while x != 0 {
+                x = x & x - 1
+                r = r + 1
+            }
+        }
+    ";
+    let popcount = jit.compile(popcount_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let popcount = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(popcount) };
+    println!("popcount(0xff00ff) = {}", popcount(0xff00ff));
+    assert_eq!(popcount(0xff00ff), 16);
+    assert_eq!(popcount(-1), 64);
+
+    // `>>` keeps the sign of what it shifts, `>>>` doesn't.
+    let shifts_code = "
+        fn halve_both(x) -> (r) {
+            signed = x >> 1
+            unsigned = x >>> 1
+            r = signed ^ unsigned
+        }
+    ";
+    let halve_both = jit.compile(shifts_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let halve_both = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(halve_both) };
+    println!("halve_both(-8) = {:#x}", halve_both(-8));
+    assert_eq!(halve_both(8), 0);
+    assert_eq!(halve_both(-8), isize::MIN);
+}
+
+fn bools(jit: &mut jit::JIT) {
+    // `true` and `false` are 1 and 0. A comparison which is only branched on
+    // stays a `b1`, without being widened to an integer first.
+    let in_range_code = "
+        fn in_range(x, lo, hi) -> (r) {
+            r = false
+            if lo <= x && !(x >= hi) {
+                r = true
+            } else {
+            }
+        }
+    ";
+    let ir = jit.ir(in_range_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("{}", ir);
+    assert_eq!(ir.matches("bint").count(), 2);
+    let in_range = jit.compile(in_range_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let in_range =
+        unsafe { mem::transmute::<*const u8, fn(isize, isize, isize) -> isize>(in_range) };
+    println!("in_range(3, 1, 5) = {}", in_range(3, 1, 5));
+    assert_eq!(in_range(3, 1, 5), 1);
+    assert_eq!(in_range(5, 1, 5), 0);
+
+    // Bools have a type of their own, though arithmetic on them produces
+    // integers.
+    let tags = jit
+        .eval_expr("typeof(true) * 100 + typeof(1 < 2 || false) * 10 + typeof(true + 1)")
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    assert_eq!(tags, 331);
+}
+
+fn floats(jit: &mut jit::JIT) {
+    // Literals with a decimal point are `f64`s, and so is any variable which
+    // is assigned one, including the return variable. Arithmetic and
+    // comparisons on them use Cranelift's floating-point instructions.
+    let circle_area_code = "
+        fn circle_area(n) -> (r) {
+            pi = 3.14159
+            radius = 0.0
+            repeat n {
+                radius = radius + 0.5
+            }
+            r = pi * radius * radius
+            if r > 100.0 {
+                r = 100.0
+            } else {
+            }
+            assert_eq(typeof(r), 2)
+        }
+    ";
+    let circle_area = jit.compile(circle_area_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let circle_area = unsafe { mem::transmute::<*const u8, fn(isize) -> f64>(circle_area) };
+    println!("circle_area(4) = {}", circle_area(4));
+    assert!((circle_area(4) - 12.56636).abs() < 1e-9);
+    assert_eq!(circle_area(100), 100.0);
+
+    // Assigning an integer to a float variable converts it, just as `as`
+    // would, whichever way it's reached.
+    let mixed_code = "
+        fn mixed(x) -> (r) {
+            if x {
+                r = 1.5
+            } else {
+                r = 2
+            }
+            y = 1.5
+            y = x
+            r = r + y
+        }
+    ";
+    let mixed = jit.compile(mixed_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let mixed = unsafe { mem::transmute::<*const u8, fn(isize) -> f64>(mixed) };
+    assert_eq!((mixed(0), mixed(3)), (2.0, 4.5));
+
+    // So does mixing an integer with a float in arithmetic or a comparison,
+    // and passing a float to an integer parameter rounds it towards zero.
+    let mixed_ops_code = "
+        fn truncated(n) -> (r) {
+            r = n
+        }
+
+        fn mixed_ops(x) -> (r) {
+            r = x + 1.5
+            if r < 3 {
+                r = r * 2
+            }
+            r = r + truncated(2.5)
+        }
+    ";
+    let program = jit.compile_program(mixed_ops_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let mixed_ops = unsafe { mem::transmute::<*const u8, fn(isize) -> f64>(program["mixed_ops"]) };
+    println!("mixed_ops(1) = {}", mixed_ops(1));
+    assert_eq!((mixed_ops(1), mixed_ops(2)), (7.0, 5.5));
+
+    // The remainder of floats, like that of integers, has the sign of the
+    // dividend.
+    let float_mod_code = "
+        fn float_mod(x: f64) -> (r) {
+            single = f32(x) % f32(2)
+            r = x % 2.0 + f64(single) * 10.0
+        }
+    ";
+    let float_mod = jit.compile(float_mod_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let float_mod = unsafe { mem::transmute::<*const u8, fn(f64) -> f64>(float_mod) };
+    assert_eq!((float_mod(5.5), float_mod(-5.5)), (16.5, -16.5));
+
+    // `f32(x)` and `f64(x)` convert between the float types, and from
+    // integers. An `f32` third, promoted back to an `f64`, shows how much
+    // precision it lost.
+    let third_code = "
+        fn third(x) -> (r) {
+            single = f32(1.0) / f32(3)
+            assert_eq(typeof(single), 5)
+            r = f64(single) * f64(x)
+        }
+    ";
+    let third = jit.compile(third_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let third = unsafe { mem::transmute::<*const u8, fn(isize) -> f64>(third) };
+    println!("third(3) = {}", third(3));
+    assert_eq!(third(3), f64::from(1.0f32 / 3.0) * 3.0);
+
+    // Each of them converts a single value.
+    let doubled = jit.compile("fn doubled(x) -> (r) {\n r = f64(x, x)\n}\n");
+    match doubled {
+        Ok(_) => println!("doubled compiled"),
+        Err(ref msg) => println!("doubled failed: {}", msg),
+    }
+    assert_eq!(
+        doubled,
+        Err("`f64` takes 1 argument but 2 were given".to_string())
+    );
+    assert!(third(3) != 1.0);
+}
+
+fn integer_types(jit: &mut jit::JIT) {
+    // Integers are 64 bits, but `i32(x)` truncates one to 32. Arithmetic on
+    // two `i32`s wraps at 32 bits, while mixing one with a 64-bit integer
+    // sign-extends it first, and assigning a 64-bit integer to an `i32`
+    // variable truncates it again.
+    let square_i32_code = "
+        fn square_i32(a) -> (r) {
+            x = i32(a)
+            assert_eq(typeof(x), 0)
+            wide = x + 0
+            assert_eq(i64(x), wide)
+            r = x * x
+            r = r + 0
+        }
+    ";
+    let square_i32 = jit.compile(square_i32_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let square_i32 = unsafe { mem::transmute::<*const u8, fn(isize) -> i32>(square_i32) };
+    println!("square_i32(46341) = {}", square_i32(46341));
+    assert_eq!(square_i32(46341), 46341i32.wrapping_mul(46341));
+    assert_eq!(square_i32(0x1_0000_0003), 9);
+
+    // Parameters and return variables can be declared with a type, which
+    // goes into the function's signature. Calls convert integer arguments to
+    // the width of the parameters they're passed to.
+    let annotated_code = "\
+        fn lerp(a: f64, b: f64, t: f64) -> (r: f64) {
+            r = a + (b - a) * t
+        }
+
+        fn low_half(x: i32) -> (r: i64) {
+            r = x
+        }
+
+        fn call_low_half(n) -> (r) {
+            r = low_half(n) * 2
+        }
+    ";
+    let program = jit.compile_program(annotated_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let lerp = unsafe { mem::transmute::<*const u8, fn(f64, f64, f64) -> f64>(program["lerp"]) };
+    println!("lerp(1, 3, 0.25) = {}", lerp(1.0, 3.0, 0.25));
+    assert_eq!(lerp(1.0, 3.0, 0.25), 1.5);
+    let low_half = unsafe { mem::transmute::<*const u8, fn(i32) -> isize>(program["low_half"]) };
+    assert_eq!(low_half(-7), -7);
+    let call_low_half =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["call_low_half"]) };
+    println!(
+        "call_low_half(0x1_ffff_ffff) = {}",
+        call_low_half(0x1_ffff_ffff)
+    );
+    assert_eq!(call_low_half(0x1_ffff_ffff), -2);
+
+    // `as` converts a value to another type, just as the conversion builtins
+    // do. Floats are rounded towards zero when they're cast to integers.
+    let average_code = "
+        fn average(a, b) -> (r) {
+            mean = (a + b) as f64 / 2.0
+            assert_eq(typeof(mean as f32), 5)
+            r = (mean * 10.0) as i64
+        }
+    ";
+    let average = jit.compile(average_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let average = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(average) };
+    println!("average(3, 4) * 10 = {}", average(3, 4));
+    assert_eq!(average(3, 4), 35);
+    assert_eq!(average(-3, -4), -35);
+
+    // `u32` and `u64` are unsigned: they're compared as unsigned, and `>>`
+    // shifts zeros into them. A `u32` is zero-extended when it's mixed with a
+    // wider integer, and mixing signed and unsigned integers of the same
+    // width is unsigned.
+    let unsigned_code = "\
+        fn below(a: u64, b) -> (r) {
+            r = a < b
+        }
+
+        fn widen(x: u32) -> (r) {
+            r = x + 0
+        }
+
+        fn halve_u64(x: u64) -> (r: u64) {
+            r = x >> 1
+        }
+    ";
+    let program = jit.compile_program(unsigned_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let below = unsafe { mem::transmute::<*const u8, fn(u64, i64) -> isize>(program["below"]) };
+    let widen = unsafe { mem::transmute::<*const u8, fn(u32) -> isize>(program["widen"]) };
+    let halve_u64 = unsafe { mem::transmute::<*const u8, fn(u64) -> u64>(program["halve_u64"]) };
+    println!("below(u64::MAX, 1) = {}", below(u64::MAX, 1));
+    assert_eq!(below(u64::MAX, 1), 0);
+    assert_eq!(below(1, -1), 1);
+    println!("widen(u32::MAX) = {}", widen(u32::MAX));
+    assert_eq!(widen(u32::MAX), 0xffff_ffff);
+    assert_eq!(halve_u64(u64::MAX), u64::MAX / 2);
+}
+
+fn division(jit: &mut jit::JIT) {
+    // Division is signed, rounding towards zero, with a remainder which has
+    // the sign of the dividend. Dividing unsigned integers is unsigned.
+    let divide_code = "\
+        fn divide(a, b) -> (r) {
+            r = a / b * 10 + a % b
+        }
+
+        fn divide_u64(a: u64, b: u64) -> (r: u64) {
+            r = a / b
+        }
+    ";
+    let program = jit.compile_program(divide_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let divide =
+        unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(program["divide"]) };
+    let divide_u64 =
+        unsafe { mem::transmute::<*const u8, fn(u64, u64) -> u64>(program["divide_u64"]) };
+    println!("divide(-7, 2) = {}", divide(-7, 2));
+    assert_eq!(divide(-7, 2), -31);
+    assert_eq!(divide(7, -2), -29);
+    assert_eq!(divide_u64(u64::MAX, 2), u64::MAX / 2);
+}
+
+fn memory(jit: &mut jit::JIT) {
+    // `array(n)` is the address of `n` integers on the stack, starting out as
+    // zero, which `load` and `store` read and write by index. This finds the
+    // primes below `n` with a sieve.
+    let count_primes_code = "
+        fn count_primes(n) -> (r) {
+            sieve = array(100)
+            i = 2
+            while i < n {
+                if load(sieve, i) == 0 {
+                    r = r + 1
+                    j = i * i
+                    while j < n {
+                        store(sieve, j, 1)
+                        j = j + i
+                    }
+                } else {
+                }
+                i = i + 1
+            }
+        }
+    ";
+    let count_primes = jit.compile(count_primes_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let count_primes = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(count_primes) };
+    println!("count_primes(100) = {}", count_primes(100));
+    assert_eq!(count_primes(100), 25);
+    assert_eq!(count_primes(10), 4);
+
+    // An array's length has to be known when it's compiled, and `load` and
+    // `store` need all of their arguments.
+    for &(code, message) in &[
+        (
+            "fn sized(n) -> (r) {\n a = array(n)\n}\n",
+            "the length of an array must be a constant from 0 to 4096",
+        ),
+        (
+            "fn unsized() -> (r) {\n a = array()\n}\n",
+            "`array` takes 1 argument but 0 were given",
+        ),
+        (
+            "fn unindexed() -> (r) {\n r = load(array(2))\n}\n",
+            "`load` takes 2 arguments but 1 were given",
+        ),
+        (
+            "fn unstored() -> (r) {\n store(array(2), 0)\n}\n",
+            "`store` takes 3 arguments but 2 were given",
+        ),
+    ] {
+        let compiled = jit.compile(code);
+        match compiled {
+            Ok(_) => println!("compiled"),
+            Err(ref msg) => println!("compiling failed: {}", msg),
+        }
+        assert_eq!(compiled, Err(message.to_string()));
+    }
+
+    // `a[i]` is shorthand for `load(a, i)`, and `a[i] = x` for `store(a, i, x)`.
+    // This fills an array with the first `n` Fibonacci numbers and returns the
+    // last one.
+    let fib_table_code = "
+        fn fib_table(n) -> (r) {
+            fib = array(64)
+            fib[1] = 1
+            i = 2
+            while i < n {
+                fib[i] = fib[i - 1] + fib[i - 2]
+                i = i + 1
+            }
+            r = fib[n - 1]
+        }
+    ";
+    let fib_table = jit.compile(fib_table_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let fib_table = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(fib_table) };
+    println!("fib_table(50) = {}", fib_table(50));
+    assert_eq!(fib_table(50), 7_778_742_049);
+    assert_eq!(fib_table(2), 1);
+
+    // `alloc(n)` is like `array(n)`, but its integers come from the host's
+    // allocator, so there can be as many as the caller asks for, and they
+    // stay allocated until they're given to `free`.
+    let sum_squares_code = "
+        fn sum_squares(n) -> (r) {
+            squares = alloc(n)
+            i = 0
+            while i < n {
+                squares[i] = i * i
+                i = i + 1
+            }
+            while i > 0 {
+                i = i - 1
+                r = r + squares[i]
+            }
+            free(squares)
+        }
+    ";
+    let sum_squares = jit.compile(sum_squares_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let sum_squares = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(sum_squares) };
+    println!("sum_squares(10000) = {}", sum_squares(10000));
+    assert_eq!(sum_squares(10000), 333_283_335_000);
+
+    // Both of them take a single argument.
+    for &(code, message) in &[
+        (
+            "fn unsized_alloc() -> (r) {\n r = alloc()\n}\n",
+            "`alloc` takes 1 argument but 0 were given",
+        ),
+        (
+            "fn double_free(a, b) -> (r) {\n free(a, b)\n}\n",
+            "`free` takes 1 argument but 2 were given",
+        ),
+    ] {
+        let compiled = jit.compile(code);
+        match compiled {
+            Ok(_) => println!("compiled"),
+            Err(ref msg) => println!("compiling failed: {}", msg),
+        }
+        assert_eq!(compiled, Err(message.to_string()));
+    }
+    assert_eq!(sum_squares(0), 0);
+}
+
+fn addresses(jit: &mut jit::JIT) {
+    // `&x` is the address of the variable `x`, and `*p` the integer at the
+    // address `p`, which can be assigned to. Adding `n` to a pointer moves it
+    // on by `n` integers, and subtracting two pointers gives the number of
+    // integers between them.
+    let pointers_code = "\
+        fn swap(p, q) -> (r) {
+            r = *p
+            *p = *q
+            *q = r
+        }
+
+        fn sorted_pair(a, b) -> (r) {
+            if a > b {
+                swap(&a, &b)
+            } else {
+            }
+            r = a * 100 + b
+        }
+
+        fn count_up(n) -> (r) {
+            xs = alloc(n)
+            p = xs
+            while p < xs + n {
+                *p = p - xs + 1
+                p = p + 1
+            }
+            while p > xs {
+                p = p - 1
+                r = r + *p
+            }
+            free(xs)
+        }
+    ";
+    let program = jit.compile_program(pointers_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let sorted_pair =
+        unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(program["sorted_pair"]) };
+    let count_up = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(program["count_up"]) };
+    println!("sorted_pair(7, 3) = {}", sorted_pair(7, 3));
+    assert_eq!(sorted_pair(7, 3), 307);
+    assert_eq!(sorted_pair(3, 7), 307);
+    println!("count_up(10) = {}", count_up(10));
+    assert_eq!(count_up(10), 55);
+}
+
+fn expressions(jit: &mut jit::JIT) {
+    // For quick experiments, a single expression can be evaluated directly.
+    let value = jit.eval_expr("2 + 3 * 4").unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("2 + 3 * 4 = {}", value);
+    assert_eq!(value, 14);
+    assert_eq!(jit.eval_expr("i32(-7) * 3"), Ok(-21));
+    let float_value = jit.eval_expr("1.5 * 2.0");
+    match float_value {
+        Ok(value) => println!("1.5 * 2.0 = {}", value),
+        Err(ref msg) => println!("1.5 * 2.0 failed: {}", msg),
+    }
+    assert!(float_value.is_err());
+
+    // The way an expression was grouped can be shown by writing it back out
+    // with every operation in parentheses. Built-in binary operators associate
+    // to the left, and parentheses group as usual.
+    for (source, grouped) in &[
+        ("2 + 3 * 4", "(2 + (3 * 4))"),
+        ("10 - 4 - 3", "((10 - 4) - 3)"),
+        ("10 - (4 - 3)", "(10 - (4 - 3))"),
+        ("-a * b / c", "(((-a) * b) / c)"),
+        ("a < b + 1 && c || d", "(((a < (b + 1)) && c) || d)"),
+        (
+            "a ? b : c ? 1 : 0x2",
+            "(if a { b } else { (if c { 1 } else { 0x2 }) })",
+        ),
+        ("x |> f |> g", "g(f(x))"),
+        ("a & 1 == b | c ^ d | e", "((a & 1) == ((b | (c ^ d)) | e))"),
+        ("1 << n - 1 >>> 2", "((1 << (n - 1)) >>> 2)"),
+        ("-a as f64 * b as i32", "(((-a) as f64) * (b as i32))"),
+        ("-a[i + 1][j] * 2", "((-a[(i + 1)][j]) * 2)"),
+        ("*p * *&x - 1", "(((*p) * (*&x)) - 1)"),
+        ("!a != !-b", "((!a) != (!(-b)))"),
+        ("(a + b) * -(c - d)", "((a + b) * (-(c - d)))"),
+        ("a -1 - -0x10 * - 2", "((a - 1) - (-0x10 * (-2)))"),
+        ("true || !false", "(true || (!false))"),
+        ("1.5 * -2.0e3", "(1.5 * -2.0e3)"),
+    ] {
+        let tree = jit.parse_expr_tree(source).unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+        println!("{} groups as {}", source, tree);
+        assert_eq!(tree, *grouped);
+    }
+
+    // Integer literals are 64 bits, so one which doesn't fit is an error
+    // rather than being truncated.
+    let big_literal_code = "\
+        fn big_literal() -> (r) {
+            r = 3000000000 * 10 + 0xFFFFFFFF
+        }
+    ";
+    let big_literal = jit.compile(big_literal_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let big_literal = unsafe { mem::transmute::<*const u8, fn() -> isize>(big_literal) };
+    assert_eq!(big_literal(), 30_000_000_000 + 0xFFFF_FFFF);
+    let too_wide = jit.parse_expr_tree("9223372036854775808");
+    match too_wide {
+        Ok(ref tree) => println!("too_wide parsed as {}", tree),
+        Err(ref msg) => println!("too_wide failed: {}", msg),
+    }
+    assert!(too_wide.is_err());
+    assert!(jit.parse_expr_tree("-9223372036854775808").is_ok());
+}
+
+fn code_bytes(jit: &mut jit::JIT) {
+    // The machine code of a compiled function can be read back, for example
+    // to checksum it. `six_times` was compiled above.
+    let bytes = jit.code_bytes("six_times").unwrap_or_else(|| {
+        eprintln!("error: six_times has not been compiled");
+        process::exit(1);
+    });
+    let checksum = bytes
+        .iter()
+        .fold(0u32, |sum, &b| sum.rotate_left(5) ^ u32::from(b));
+    println!(
+        "six_times is {} bytes, with checksum {:08x}",
+        bytes.len(),
+        checksum
+    );
+    assert_eq!(Some(bytes.len()), jit.code_size("six_times"));
+    assert!(jit.code_bytes("not_compiled").is_none());
+}
+
+fn determinism() {
+    // Compiling the same source gives the same machine code every time, at
+    // least for functions without relocations, whose code doesn't depend on
+    // where anything else was put in memory.
+    let mut codes = Vec::new();
+    for _ in 0..2 {
+        let mut fresh_jit = jit::JIT::new();
+        fresh_jit
+            .compile_program(ITERATIVE_FIB_CODE)
+            .unwrap_or_else(|msg| {
+                eprintln!("error: {}", msg);
+                process::exit(1);
+            });
+        codes.push(fresh_jit.code_bytes("iterative_fib").unwrap().to_vec());
+    }
+    println!(
+        "iterative_fib compiles the same twice: {}",
+        codes[0] == codes[1]
+    );
+    assert_eq!(codes[0], codes[1]);
+}
+
+fn deferred_finalization(jit: &mut jit::JIT) {
+    // A function can be defined without being finalized, so that it can call
+    // a function which hasn't been written yet. Until that one's defined too,
+    // the call is a pending relocation, which finalizing would have to find
+    // in the host process instead.
+    jit.define("fn calls_g(x) -> (r) {\nr = g(x) + 1\n}\n")
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    let pending = jit.pending_relocations("calls_g");
+    println!("calls_g is waiting for: {}", pending.join(", "));
+    assert_eq!(pending, ["g"]);
+
+    // Compiling `g` finalizes `calls_g` along with it.
+    jit.compile("fn g(x) -> (r) {\nr = x * 2\n}\n")
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    assert!(jit.pending_relocations("calls_g").is_empty());
+    let calls_g = jit.code_bytes("calls_g").unwrap().as_ptr();
+    let calls_g = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(calls_g) };
+    println!("calls_g(20) = {}", calls_g(20));
+    assert_eq!(calls_g(20), 41);
+}
+
+fn dynamic_calls(jit: &mut jit::JIT) {
+    // Transmuting to the wrong function type is undefined behavior. When the
+    // arguments aren't known until runtime, `compile_dynamic` produces a
+    // function which checks the number of arguments before calling.
+    let add_code = "\
+        fn add(a, b) -> (r) {
+            r = a + b
+        }
+    ";
+    let add = jit.compile_dynamic(add_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    for args in &[&[1, 2][..], &[1, 2, 3][..]] {
+        match add.call(args) {
+            Ok(result) => println!("add{:?} = {}", args, result),
+            Err(msg) => println!("add{:?} failed: {}", args, msg),
+        }
+    }
+    assert_eq!(add.call(&[1, 2]), Ok(3));
+    assert!(add.call(&[1, 2, 3]).is_err());
+
+    // Only integers can be passed in a slice of `isize`s, so a function
+    // with parameters or a result of other types can't be called this way.
+    let scale_code = "\
+        fn scale_dyn(a: f64) -> (r) {
+            r = a * 2.0
+        }
+    ";
+    let scale_dyn = jit.compile_dynamic(scale_code);
+    if let Err(ref msg) = scale_dyn {
+        println!("scale_dyn failed: {}", msg);
+    }
+    assert!(scale_dyn.is_err());
+}
+
+fn introspection(jit: &mut jit::JIT) {
+    // The control flow graph of a function can be exported for Graphviz, with
+    // one node per EBB. Since an EBB may contain branches out of it, the then
+    // arm of an if/else stays in the current EBB, and only the else arm and
+    // the merge point get their own. Likewise, a loop's header and body share
+    // an EBB, its (here empty) else-body gets another, and the loop's exit a
+    // third, since a `break` would skip the else-body.
+    let shape_code = "\
+        fn shape(n) -> (r) {
+            r = if n > 0 {
+                1
+            } else {
+                2
+            }
+            while n > 0 {
+                n = n - 1
+            }
+        }
+    ";
+    let dot = jit.cfg_dot(shape_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    print!("{}", dot);
+    assert_eq!(dot.matches(";\n").count() - dot.matches(" -> ").count(), 6);
+    assert_eq!(dot.matches(" -> ").count(), 7);
+
+    // Each toy variable is declared as a Cranelift `Variable`, numbered in
+    // the order they're declared, and Cranelift builds SSA values from the
+    // definitions and uses of each.
+    let swap_code = "\
+        fn swap_sum(a, b) -> (r) {
+            t = a
+            a = b
+            b = t
+            r = a * 10 + b
+        }
+    ";
+    let variables = jit.variable_map(swap_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    for (name, index, ty) in &variables {
+        println!("{} -> var{}: {}", name, index, ty);
+    }
+    let names: Vec<_> = variables.iter().map(|v| v.0.as_str()).collect();
+    assert_eq!(names, ["a", "b", "r", "t"]);
+    assert!(variables.iter().enumerate().all(|(i, v)| v.1 == i));
+    let typed_code = "\
+        fn typed(a, b: f64) -> (r) {
+            t = i32(a)
+            r = b
+        }
+    ";
+    let variables = jit.variable_map(typed_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let types: Vec<_> = variables.iter().map(|v| v.2.to_string()).collect();
+    assert_eq!(types, ["i64", "f64", "f64", "i32"]);
+}
+
+fn calling_convention(jit: &mut jit::JIT) {
+    // The calling convention decides where each parameter is passed. On
+    // SystemV x86-64, the first six integer parameters are in registers.
+    let many_params_code = "\
+        fn many_params(a, b, c, d, e, f, g, h) -> (r) {
+            r = a + b + c + d + e + f + g + h
+        }
+    ";
+    let locations = jit.param_locations(many_params_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("many_params parameters: {}", locations.join(", "));
+    assert!(locations[..6].iter().all(|l| l.starts_with('%')));
+}
+
+fn interpreter(jit: &mut jit::JIT) {
+    // The same functions can also be run by interpreting their ASTs directly,
+    // which is handy for checking that the JIT computes the right answers.
+    for &(name, code) in &[
+        ("recursive_fib", RECURSIVE_FIB_CODE),
+        ("iterative_fib", ITERATIVE_FIB_CODE),
+    ] {
+        let result = jit.interpret(code, &[10]).unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+        println!("interpreted {}(10) = {}", name, result);
+        assert_eq!(result, 55);
+    }
+    for &(n, class) in &[(5, 1), (15, 2), (25, 3)] {
+        let result = jit.interpret(CLASSIFY_CODE, &[n]).unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+        assert_eq!(result, class);
+    }
+
+    // Doing that over a whole corpus of programs is a good way to catch bugs
+    // in either implementation.
+    let runs = difftest::run_corpus().unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("differential testing: {} runs agree", runs);
+}
+
+fn diagnostics(jit: &mut jit::JIT) {
+    // The JIT can also look for mistakes without compiling anything. Here,
+    // the first value assigned to `t` is overwritten before it's ever read.
+    let overwrite_code = "\
+        fn overwrite(a) -> (r) {
+            t = a * 2
+            t = a * 3
+            r = t
+        }
+    ";
+
+    let warnings = jit.dead_stores(overwrite_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    for warning in warnings {
+        println!("warning: {}", warning);
+    }
+
+    // A `let` binding which is never used is reported by the name it binds.
+    let unused_let_code = "\
+        fn unused_let(a) -> (r) {
+            let x = a * 2
+            r = a
+        }
+    ";
+    let warnings = jit.dead_stores(unused_let_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    assert_eq!(warnings, ["value assigned to `x` is never read"]);
+
+    // The variable of a `for` loop which only counts isn't reported, since
+    // it's the loop which assigns it, but an assignment written in the body
+    // is.
+    let count_up_code = "\
+        fn count_up(n) -> (r) {
+            for i in 0 .. n {
+                r = r + 1
+            }
+        }
+    ";
+    let warnings = jit.dead_stores(count_up_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    assert!(warnings.is_empty());
+    let reset_code = "\
+        fn reset(n) -> (r) {
+            for i in 0 .. n {
+                i = 0
+                r = r + 1
+            }
+        }
+    ";
+    let warnings = jit.dead_stores(reset_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    assert_eq!(warnings, ["value assigned to `i` is never read"]);
+
+    // Some mistakes are found when compiling. Here, `forever` has no base
+    // case, so calling it would never return.
+    let forever_code = "\
+        fn forever(n) -> (r) {
+            r = forever(n) + 1
+        }
+    ";
+    jit.compile(forever_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let warnings = jit.take_warnings();
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+    assert_eq!(warnings.len(), 1);
+
+    // Whereas `recursive_fib`, which has one, compiles without warnings.
+    let mut fib_jit = jit::JIT::new();
+    fib_jit.compile(RECURSIVE_FIB_CODE).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    assert!(fib_jit.take_warnings().is_empty());
+
+    // Similarly, identifiers which are never bound are probably typos. Here,
+    // `y` should have been `x`, while `VERSION` is a constant defined above.
+    let typo_code = "\
+        fn typo(x) -> (r) {
+            r = y * 2 + VERSION
+        }
+    ";
+
+    let free = jit.free_identifiers(typo_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("free identifiers: {:?}", free);
+    assert_eq!(free, ["y"]);
+
+    // Tools can have all of these checks made at once, along with compiling,
+    // and get what they find back as JSON.
+    let sloppy_code = "\
+        fn sloppy(x) -> (r) {
+            t = x
+            r = z + 1
+        }
+    ";
+    let (code, diagnostics) = jit.compile_with_diagnostics(sloppy_code);
+    println!("sloppy diagnostics: {}", diagnostics);
+    assert!(code.is_none());
+    assert!(diagnostics.contains(r#""severity": "warning", "kind": "dead_store""#));
+    assert!(diagnostics.contains(r#""severity": "error", "kind": "undefined""#));
+    let (code, diagnostics) = jit.compile_with_diagnostics("fn unfinished(x) -> (r) {\n");
+    println!("unfinished diagnostics: {}", diagnostics);
+    assert!(code.is_none());
+    assert!(diagnostics.contains(r#""kind": "parse""#));
+    let (code, diagnostics) = jit.compile_with_diagnostics("fn tidy(x) -> (r) {\nr = x\n}\n");
+    assert!(code.is_some());
+    assert_eq!(diagnostics, "[]");
+
+    // Their warnings name variables as they're written, and leave out the
+    // ones `for` loops assign.
+    let unused_bindings_code = "\
+        fn unused_bindings(n) -> (r) {
+            let x = n * 2
+            for i in 0 .. n {
+                r = r + 1
+            }
+        }
+    ";
+    let (code, diagnostics) = jit.compile_with_diagnostics(unused_bindings_code);
+    println!("unused_bindings diagnostics: {}", diagnostics);
+    assert!(code.is_some());
+    assert_eq!(
+        diagnostics,
+        r#"[{"severity": "warning", "kind": "dead_store", "message": "value assigned to `x` is never read", "span": null}]"#
+    );
+}
+
+fn assertions(jit: &mut jit::JIT) {
+    // `assert_eq` traps when its operands differ, which lets toy programs
+    // check themselves.
+    let checked_code = "\
+        fn checked(a, b) -> (r) {
+            assert_eq(a * 2, b)
+            r = b
+        }
+    ";
+
+    // Same as above.
+    let checked = jit.compile(checked_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let checked = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(checked) };
+    println!("checked(2, 4) = {}", checked(2, 4));
+
+    // A trap takes the whole process down with it, so the failing case is run
+    // by a copy of this program, which should be killed by a signal.
+    if env::args().any(|arg| arg == "--assert-fails") {
+        checked(2, 5);
+        process::exit(0);
+    }
+    let trapped = traps("--assert-fails");
+    println!("checked(2, 5) trapped: {}", trapped);
+    assert!(trapped);
+
+    // It needs both of its operands.
+    let half_checked = jit.compile("fn half_checked(a) -> (r) {\n assert_eq(a)\n}\n");
+    match half_checked {
+        Ok(_) => println!("half_checked compiled"),
+        Err(ref msg) => println!("half_checked failed: {}", msg),
+    }
+    assert_eq!(
+        half_checked,
+        Err("`assert_eq` takes 2 arguments but 1 were given".to_string())
+    );
+}
+
+fn negation(jit: &mut jit::JIT) {
+    // Negating the most negative integer overflows. By default it wraps
+    // around, but it can be made to trap instead.
+    let negate_code = "\
+        fn negate(x) -> (r) {
+            r = -x
+        }
+    ";
+    let negate = jit.compile(negate_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let negate = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(negate) };
+    println!("negate(isize::MIN) = {}", negate(isize::MIN));
+    assert_eq!(negate(isize::MIN), isize::MIN);
+
+    jit.set_checked_negation(true);
+    let checked_negate_code = "\
+        fn checked_negate(x) -> (r) {
+            r = -x
+        }
+    ";
+    let checked_negate = jit.compile(checked_negate_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    jit.set_checked_negation(false);
+    let checked_negate = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(checked_negate) };
+    println!("checked_negate(7) = {}", checked_negate(7));
+    if env::args().any(|arg| arg == "--negation-overflows") {
+        checked_negate(isize::MIN);
+        process::exit(0);
+    }
+    let trapped = traps("--negation-overflows");
+    println!("checked_negate(isize::MIN) trapped: {}", trapped);
+    assert!(trapped);
+}
+
+fn overflow(jit: &mut jit::JIT) {
+    // Rather than wrapping around, overflowing arithmetic can call a handler,
+    // which is told the operation and its operands.
+    jit.set_overflow_handler(Some(report_overflow));
+    let product_code = "\
+        fn product(a, b) -> (r) {
+            r = a * b + 1
+        }
+    ";
+    let product = jit.compile(product_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    jit.set_overflow_handler(None);
+    let product = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(product) };
+    println!("product(6, 7) = {}", product(6, 7));
+    assert_eq!(OVERFLOWED_OP.load(Ordering::SeqCst), 0);
+    let big = isize::MAX / 3;
+    println!("product({}, 4) = {}", big, product(big, 4));
+    println!(
+        "overflow: op {}, operands {} and {}",
+        OVERFLOWED_OP.load(Ordering::SeqCst),
+        OVERFLOWED_LHS.load(Ordering::SeqCst),
+        OVERFLOWED_RHS.load(Ordering::SeqCst)
+    );
+    assert_eq!(product(big, 4), 1);
+    assert_eq!(OVERFLOWED_OP.load(Ordering::SeqCst), jit::OVERFLOW_MUL);
+    assert_eq!(OVERFLOWED_LHS.load(Ordering::SeqCst), big);
+    assert_eq!(OVERFLOWED_RHS.load(Ordering::SeqCst), 4);
+    assert_eq!(product(isize::MIN, -1), 1);
+    assert_eq!(product(-big, 3), -big * 3 + 1);
+}
+
+fn while_else(jit: &mut jit::JIT) {
+    // A while loop's else-body runs when its condition becomes false, but not
+    // when it's left through a `break`. This finds the smallest divisor of n,
+    // or returns 0 if n is prime.
+    let smallest_divisor_code = "\
+        fn smallest_divisor(n) -> (r) {
+            d = 2
+            while d < n {
+                q = n / d
+                if q * d == n {
+                    r = d
+                    break
+                } else {
+                }
+                d = d + 1
+            } else {
+                r = 0
+            }
+        }
+    ";
+    let smallest_divisor = jit.compile(smallest_divisor_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let smallest_divisor =
+        unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(smallest_divisor) };
+    println!("smallest_divisor(91) = {}", smallest_divisor(91));
+    println!("smallest_divisor(97) = {}", smallest_divisor(97));
+    assert_eq!(smallest_divisor(91), 7);
+    assert_eq!(smallest_divisor(97), 0);
+
+    // A `break` outside of any loop has nowhere to go, so it's an error.
+    let stray_break_code = "fn stray_break() -> (r) {\n break\n}\n";
+    let stray_break = jit.compile(stray_break_code);
+    match stray_break {
+        Ok(_) => println!("stray_break compiled"),
+        Err(ref msg) => println!("stray_break failed: {}", msg),
+    }
+    assert_eq!(stray_break, Err("`break` outside of a loop".to_string()));
+    assert!(jit.interpret(stray_break_code, &[]).is_err());
+}
+
+fn tail_returns(jit: &mut jit::JIT) {
+    // When a function ends by assigning an if-else to its return variable,
+    // each arm returns its own value, so there's no merge block to jump to,
+    // even for an if-else nested at the end of an arm.
+    let pick_code = "\
+        fn pick(n) -> (r) {
+            r = if n < 0 {
+                0 - 1
+            } else {
+                if n == 0 {
+                    0
+                } else {
+                    1
+                }
+            }
+        }
+    ";
+    let ir = jit.ir(pick_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("{}", ir);
+    assert_eq!(ir.matches(" return ").count(), 3);
+    assert_eq!(ir.matches("jump").count(), 0);
+    let ebbs = ir.lines().filter(|line| line.starts_with("ebb")).count();
+    assert_eq!(ebbs, 3);
+    let pick = jit.compile(pick_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let pick = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(pick) };
+    println!(
+        "pick(-5) = {}, pick(0) = {}, pick(5) = {}",
+        pick(-5),
+        pick(0),
+        pick(5)
+    );
+    assert_eq!((pick(-5), pick(0), pick(5)), (-1, 0, 1));
+
+    // Each arm's value is converted to the type of the return variable, as
+    // if it had been assigned to it. Arms of different types make an
+    // integer, so the `f64` arm is rounded towards zero.
+    let pick_mixed_code = "\
+        fn pick_mixed(x) -> (r) {
+            r = if x {
+                1.5
+            } else {
+                2
+            }
+        }
+    ";
+    let pick_mixed = jit.compile(pick_mixed_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let pick_mixed = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(pick_mixed) };
+    assert_eq!((pick_mixed(1), pick_mixed(0)), (1, 2));
+}
+
+fn constant_folding(jit: &mut jit::JIT) {
+    // Conditions which are constants are folded, so an arm which can never
+    // run isn't translated at all, and neither is the test of a loop which
+    // can only be left by a `break`.
+    let spin_code = "\
+        fn spin(n) -> (r) {
+            while 1 {
+                if 0 {
+                    break
+                } else {
+                }
+                r = r + 1
+                if r >= n {
+                    break
+                } else {
+                }
+            }
+        }
+    ";
+    let ir = jit.ir(spin_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("{}", ir);
+    assert_eq!(ir.matches("brz").count(), 1);
+    let spin = jit.compile(spin_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let spin = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(spin) };
+    println!("spin(7) = {}", spin(7));
+    assert_eq!(spin(7), 7);
+}
+
+fn loops(jit: &mut jit::JIT) {
+    // `repeat n { ... }` runs its body n times. When n is a small constant,
+    // the body is simply repeated that many times in the generated code,
+    // with no loop at all.
+    let thrice_code = "\
+        fn thrice(x) -> (r) {
+            r = x
+            repeat 3 {
+                r = r + 1
+            }
+        }
+    ";
+    let ir = jit.ir(thrice_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    println!("{}", ir);
+    assert_eq!(ir.matches(" iadd ").count(), 3);
+    assert!(!ir.contains("brnz"));
+    let thrice = jit.compile(thrice_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let thrice = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(thrice) };
+    println!("thrice(10) = {}", thrice(10));
+    assert_eq!(thrice(10), 13);
+
+    // Otherwise, it's a loop which counts down.
+    let times_code = "\
+        fn times(a, b) -> (r) {
+            repeat b {
+                r = r + a
+            }
+        }
+    ";
+    let times = jit.compile(times_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let times = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(times) };
+    println!("times(6, 7) = {}", times(6, 7));
+    assert_eq!((times(6, 7), times(6, 0), times(6, -1)), (42, 0, 0));
+
+    // `continue` skips the rest of the body: in a `while` loop, back to the
+    // condition, and in a `repeat` loop, on to the next iteration.
+    let continue_code = "\
+        fn odd_sum(n) -> (r) {
+            i = 0
+            while i < n {
+                i = i + 1
+                if i % 2 == 0 {
+                    continue
+                }
+                r = r + i
+            }
+            repeat n {
+                if n > 2 {
+                    continue
+                }
+                r = r + 1000
+            }
+        }
+    ";
+    let odd_sum = jit.compile(continue_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let odd_sum = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(odd_sum) };
+    println!("odd_sum(10) = {}", odd_sum(10));
+    assert_eq!((odd_sum(10), odd_sum(2)), (25, 2001));
+
+    // Like a stray `break`, a `continue` outside of any loop is an error.
+    let stray_continue_code = "fn stray_continue() -> (r) {\n continue\n}\n";
+    let stray_continue = jit.compile(stray_continue_code);
+    match stray_continue {
+        Ok(_) => println!("stray_continue compiled"),
+        Err(ref msg) => println!("stray_continue failed: {}", msg),
+    }
+    assert_eq!(
+        stray_continue,
+        Err("`continue` outside of a loop".to_string())
+    );
+
+    // `for i in a .. b { ... }` counts `i` from `a` up to, but not including,
+    // `b`, which is only evaluated once.
+    let for_code = "\
+        fn sum_range(a, b) -> (r) {
+            for i in a .. b {
+                r = r + i
+                b = b + 1
+            }
+        }
+    ";
+    let sum_range = jit.compile(for_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let sum_range = unsafe { mem::transmute::<*const u8, fn(isize, isize) -> isize>(sum_range) };
+    println!("sum_range(1, 5) = {}", sum_range(1, 5));
+    assert_eq!((sum_range(1, 5), sum_range(5, 1)), (10, 0));
+
+    // `do { ... } while c` tests its condition after the body, so the body
+    // runs at least once.
+    let do_while_code = "\
+        fn digits(n) -> (r) {
+            do {
+                r = r + 1
+                n = n / 10
+            } while n != 0
+        }
+    ";
+    let digits = jit.compile(do_while_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let digits = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(digits) };
+    println!("digits(12345) = {}", digits(12345));
+    assert_eq!((digits(12345), digits(0), digits(-70)), (5, 1, 2));
+}
+
+fn gotos(jit: &mut jit::JIT) {
+    // `goto` jumps to a label, which gets an EBB of its own. Labels can only
+    // be at the top level of a function, so a jump never lands inside an
+    // if/else or a loop.
+    let goto_loop_code = "\
+        fn goto_loop(n) -> (r) {
+            i = 0
+        again:
+            if i < n {
+                r = r + 2
+                i = i + 1
+                goto again
+            } else {
+            }
+        }
+    ";
+    let goto_loop = jit.compile(goto_loop_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let goto_loop = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(goto_loop) };
+    println!("goto_loop(5) = {}", goto_loop(5));
+    assert_eq!(goto_loop(5), 10);
+
+    let nested_label_code = "\
+        fn nested_label(n) -> (r) {
+            while n > 0 {
+            inside:
+                n = n - 1
+            }
+            goto inside
+        }
+    ";
+    let nested_label = jit.compile(nested_label_code);
+    match nested_label {
+        Ok(_) => println!("nested_label compiled"),
+        Err(ref msg) => println!("nested_label failed: {}", msg),
+    }
+    assert!(nested_label.is_err());
+}
+
+fn stats(jit: &mut jit::JIT) {
+    // Some measurements of each function's translation are kept, to help
+    // find out why a function is expensive to compile.
+    let mut wide_code = "fn wide(x) -> (r) {\nv0 = x\n".to_string();
+    for i in 1..50 {
+        wide_code.push_str(&format!("v{} = v{} + {}\n", i, i - 1, i));
+    }
+    wide_code.push_str("r = v49\n}\n");
+    let wide = jit.compile(&wide_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let wide = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(wide) };
+    assert_eq!(wide(0), 49 * 50 / 2);
+    let small_stats = jit.compile_stats("triple").unwrap();
+    let wide_stats = jit.compile_stats("wide").unwrap();
+    println!("triple: {:?}", small_stats);
+    println!("wide: {:?}", wide_stats);
+    assert_eq!(small_stats.peak_variables, 2);
+    assert_eq!(wide_stats.peak_variables, 52);
+    assert!(wide_stats.instructions > small_stats.instructions);
+
+    // An `if` without an `else` only needs a block to merge into.
+    let at_most_code = "\
+        fn at_most(x) -> (r) {
+            r = x
+            if r > 10 {
+                r = 10
+            }
+        }
+    ";
+    let at_most = jit.compile(at_most_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let at_most = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(at_most) };
+    assert_eq!(at_most(3), 3);
+    assert_eq!(at_most(30), 10);
+    assert_eq!(jit.compile_stats("at_most").unwrap().ebbs, 2);
+}
+
+fn cfg_attributes(jit: &mut jit::JIT) {
+    // Statements and functions marked `#[cfg(flag)]` are only kept when the
+    // host has enabled `flag`, so the same source can be built with or
+    // without extra checks.
+    let mixed_code = "\
+        fn NAME(x) -> (r) {
+            r = x / 2
+            #[cfg(debug)]
+            r = r + 1000
+        }
+    ";
+    let halved = jit
+        .compile(&mixed_code.replace("NAME", "halved"))
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    let halved = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(halved) };
+    jit.set_cfg_flags(&["debug"]);
+    let debug_halved = jit
+        .compile(&mixed_code.replace("NAME", "debug_halved"))
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    let debug_halved = unsafe { mem::transmute::<*const u8, fn(isize) -> isize>(debug_halved) };
+    jit.set_cfg_flags(&[]);
+    println!(
+        "halved(10) = {}, debug_halved(10) = {}",
+        halved(10),
+        debug_halved(10)
+    );
+    assert_eq!(halved(10), 5);
+    assert_eq!(debug_halved(10), 1005);
+    let debug_only = jit.compile("#[cfg(debug)]\nfn debug_only() -> (r) {\n}\n");
+    match debug_only {
+        Ok(_) => println!("debug_only compiled"),
+        Err(ref msg) => println!("debug_only failed: {}", msg),
+    }
+    assert!(debug_only.is_err());
+}
+
+fn source_limits(jit: &mut jit::JIT) {
+    // The length of the source which will be compiled can be limited too, to
+    // guard against being given something enormous.
+    let short_code = "fn short() -> (r) {\nr = 1\n}\n";
+    let long_code = format!("fn long() -> (r) {{\nr = 1{}\n}}\n", " + 1".repeat(100));
+    jit.set_max_source_len(Some(64));
+    let short = jit.compile(short_code);
+    let long = jit.compile(&long_code);
+    jit.set_max_source_len(None);
+    match long {
+        Ok(_) => println!("long compiled"),
+        Err(ref msg) => println!("long failed: {}", msg),
+    }
+    assert!(short.is_ok());
+    assert!(long.is_err());
+}
+
+fn ebb_limits(jit: &mut jit::JIT) {
+    // The number of EBBs a function may have can be limited. Each if/else
+    // adds two, for its else arm and its merge point, so deeply nested ones
+    // go over the limit.
+    let mut nested_code = "fn nested(n) -> (r) {\n".to_string();
+    for _ in 0..20 {
+        nested_code.push_str("r = if n > r {\nr + 1\n} else {\n");
+    }
+    nested_code.push_str("r\n");
+    for _ in 0..20 {
+        nested_code.push_str("}\n");
+    }
+    nested_code.push_str("}\n");
+    jit.set_max_ebbs(Some(16));
+    let nested = jit.compile(&nested_code);
+    jit.set_max_ebbs(None);
+    match nested {
+        Ok(_) => println!("nested compiled"),
+        Err(ref msg) => println!("nested failed: {}", msg),
+    }
+    assert!(nested.is_err());
+}
+
+fn strings(jit: &mut jit::JIT) {
+    // A string literal is the address of its bytes, followed by a NUL, so a
+    // function can return one for the host to read.
+    let greeting_code = r#"
+        fn greeting() -> (r) {
+            r = "say \"hi\"\n"
+        }
+    "#;
+    let greeting = jit.compile(greeting_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let greeting = unsafe { mem::transmute::<*const u8, fn() -> *const u8>(greeting) };
+    let expected = b"say \"hi\"\n\0";
+    let bytes = unsafe { slice::from_raw_parts(greeting(), expected.len()) };
+    println!("greeting() points to {:?}", String::from_utf8_lossy(bytes));
+    assert_eq!(bytes, expected);
+
+    // The length of a string literal is known when it's compiled. Escapes
+    // count as the bytes they stand for.
+    let lengths = jit
+        .eval_expr(r#"len("hello") * 10 + len("a\nb")"#)
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+    println!("len(\"hello\") * 10 + len(\"a\\nb\") = {}", lengths);
+    assert_eq!(lengths, 53);
+    let non_literal = jit.eval_expr("len(5)");
+    if let Err(ref msg) = non_literal {
+        println!("len(5) failed: {}", msg);
+    }
+    assert_eq!(non_literal, Err("`len` takes a string literal".to_string()));
+}
+
+fn hello(jit: &mut jit::JIT) {
+    // Let's say hello, by calling into libc. The puts function is resolved by
+    // dlsym to the libc function, and the string &hello_string is defined below.
+    let hello_code = "\
+        fn hello() -> (r) {
+            puts(&hello_string)
+        }
+    ";
+
+    jit.create_data("hello_string", "hello world!\0".as_bytes().to_vec())
+        .unwrap_or_else(|msg| {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        });
+
+    // Same as above.
+    let hello = jit.compile(hello_code).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    let hello = unsafe { mem::transmute::<*const u8, fn() -> isize>(hello) };
+
+    // And we can now call it! `puts`'s result isn't assigned to `r`, so the
+    // function returns zero.
+    assert_eq!(hello(), 0);
+}
+
+/// Run a copy of this program with the given argument, which should make it
+/// call a function which traps, and report whether it was killed by a signal.
+fn traps(arg: &str) -> bool {
+    let status = env::current_exe()
+        .and_then(|exe| process::Command::new(exe).arg(arg).output())
+        .unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        })
+        .status;
+    status.code().is_none()
+}
+
+/// The number of calls to `counted`, updated by the JIT'd code itself.
+static CALLS: AtomicIsize = AtomicIsize::new(0);
+
+/// The number of times the right operand of `&&` or `||` has been evaluated
+/// in `both`, counted by `count_rhs`.
+static RHS_EVALUATIONS: AtomicIsize = AtomicIsize::new(0);
+
+extern "C" fn count_rhs() {
+    RHS_EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The operation and operands of the last overflow reported to
+/// `report_overflow`.
+static OVERFLOWED_OP: AtomicIsize = AtomicIsize::new(0);
+static OVERFLOWED_LHS: AtomicIsize = AtomicIsize::new(0);
+static OVERFLOWED_RHS: AtomicIsize = AtomicIsize::new(0);
+
+/// An overflow handler, which records the overflow and makes the result of
+/// the operation zero.
+extern "C" fn report_overflow(op: isize, lhs: isize, rhs: isize) -> isize {
+    OVERFLOWED_OP.store(op, Ordering::SeqCst);
+    OVERFLOWED_LHS.store(lhs, Ordering::SeqCst);
+    OVERFLOWED_RHS.store(rhs, Ordering::SeqCst);
+    0
+}
+
+/// A host function for toy programs to call, which takes a mix of types.
+extern "C" fn scale(x: i32, factor: f64) -> f64 {
+    f64::from(x) * factor
 }